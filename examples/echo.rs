@@ -1,11 +1,10 @@
-extern crate mio;
 extern crate mioco;
 extern crate env_logger;
 
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::io::{Read, Write};
-use mio::tcp::{TcpSocket};
+use mioco::net::TcpListener;
 
 const DEFAULT_LISTEN_ADDR : &'static str = "127.0.0.1:5555";
 
@@ -19,19 +18,14 @@ fn main() {
     mioco::start(move |mioco| {
         let addr = listend_addr();
 
-        let sock = try!(TcpSocket::v4());
-        try!(sock.bind(&addr));
-        let sock = try!(sock.listen(1024));
+        let listener = try!(TcpListener::bind(&addr));
 
-        println!("Starting tcp echo server on {:?}", sock.local_addr().unwrap());
-        let sock = mioco.wrap(sock);
+        println!("Starting tcp echo server on {:?}", try!(listener.local_addr()));
 
         loop {
-            let conn = try!(sock.accept());
-
-            mioco.spawn(move |mioco| {
-                let mut conn = mioco.wrap(conn);
+            let (mut conn, _) = try!(listener.accept());
 
+            mioco.spawn(move |_| {
                 let mut buf = [0u8; 1024 * 16];
                 loop {
                     let size = try!(conn.read(&mut buf));
@@ -43,7 +37,7 @@ fn main() {
                 }
 
                 Ok(())
-            })
+            });
         }
     });
 }
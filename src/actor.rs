@@ -0,0 +1,229 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Actors: coroutines handling messages from a typed mailbox
+//!
+//! `spawn_actor()` runs an `Actor` in a new coroutine and returns its
+//! `Address`. Addresses can be cloned and sent to other coroutines or
+//! threads. The actor finishes when all of its addresses are dropped,
+//! or when handling a message fails.
+//!
+//! ```ignore
+//! enum Msg {
+//!     Add(u64),
+//!     Get(Reply<u64>),
+//! }
+//!
+//! struct Counter(u64);
+//!
+//! impl Actor for Counter {
+//!     type Message = Msg;
+//!
+//!     fn handle(&mut self, _ : &mut MiocoHandle, msg : Msg) -> io::Result<()> {
+//!         match msg {
+//!             Msg::Add(n) => self.0 += n,
+//!             Msg::Get(reply) => reply.send(self.0),
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let counter = try!(spawn_actor(mioco, Counter(0)));
+//! try!(counter.send(Msg::Add(2)));
+//! let value = try!(counter.call(mioco, Msg::Get));
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use mio::unix::PipeWriter;
+use nix;
+
+use super::MiocoHandle;
+use super::eventfd::{EventFd, Notifier};
+use super::thread_pool;
+
+/// Coroutine handling messages sent to its `Address`
+pub trait Actor : 'static {
+    /// Type of messages handled by the actor
+    type Message : Send + 'static;
+
+    /// Called in the actor coroutine before handling any messages
+    fn started(&mut self, _mioco : &mut MiocoHandle) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Handle a message
+    ///
+    /// Returning an error finishes the actor.
+    fn handle(&mut self, mioco : &mut MiocoHandle, msg : Self::Message) -> io::Result<()>;
+}
+
+struct Mailbox<M> {
+    queue : Mutex<Queue<M>>,
+    notifier : Notifier,
+    /// Number of `Address`-es
+    senders : AtomicUsize,
+}
+
+struct Queue<M> {
+    messages : VecDeque<M>,
+    /// Actor has finished; no messages are accepted anymore
+    closed : bool,
+}
+
+/// Address of an actor, used to send it messages
+///
+/// Can be sent to other threads.
+pub struct Address<M : Send + 'static> {
+    inn : Arc<Mailbox<M>>,
+}
+
+impl<M : Send + 'static> Address<M> {
+    /// Send a message to the actor
+    ///
+    /// Does not block. Fails if the actor has finished.
+    pub fn send(&self, msg : M) -> io::Result<()> {
+        {
+            let mut queue = self.inn.queue.lock().unwrap();
+            if queue.closed {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "actor has finished"));
+            }
+            queue.messages.push_back(msg);
+        }
+
+        self.inn.notifier.notify(1)
+    }
+
+    /// Send a message built by `f` around a `Reply`, and block the current
+    /// coroutine until the actor replies
+    pub fn call<R, F>(&self, mioco : &MiocoHandle, f : F) -> io::Result<R>
+        where F : FnOnce(Reply<R>) -> M,
+              R : Send + 'static {
+            let (mut reader, writer) = try!(thread_pool::completion(&mioco.coroutine));
+            let slot = Arc::new(Mutex::new(None));
+
+            try!(self.send(f(Reply {
+                slot: slot.clone(),
+                waker: writer,
+            })));
+
+            // Pipe might contain leftover notifications, so don't trust
+            // the wakeup itself - check the slot.
+            let mut buf = [0u8; 64];
+            loop {
+                if let Some(res) = slot.lock().unwrap().take() {
+                    return res;
+                }
+                try!(reader.read(&mut buf));
+            }
+        }
+}
+
+impl<M : Send + 'static> Clone for Address<M> {
+    fn clone(&self) -> Self {
+        self.inn.senders.fetch_add(1, Ordering::SeqCst);
+        Address {
+            inn: self.inn.clone(),
+        }
+    }
+}
+
+impl<M : Send + 'static> Drop for Address<M> {
+    fn drop(&mut self) {
+        if self.inn.senders.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // wake up the actor, so it can finish
+            let _ = self.inn.notifier.notify(1);
+        }
+    }
+}
+
+/// Handle for replying to `Address::call()`
+///
+/// Dropping it without replying makes the call fail.
+pub struct Reply<R : Send + 'static> {
+    slot : Arc<Mutex<Option<io::Result<R>>>>,
+    waker : Arc<PipeWriter>,
+}
+
+impl<R : Send + 'static> Reply<R> {
+    /// Send reply to the caller
+    pub fn send(self, value : R) {
+        *self.slot.lock().unwrap() = Some(Ok(value));
+    }
+}
+
+impl<R : Send + 'static> Drop for Reply<R> {
+    fn drop(&mut self) {
+        {
+            let mut slot = self.slot.lock().unwrap();
+            if slot.is_none() {
+                *slot = Some(Err(io::Error::new(io::ErrorKind::BrokenPipe, "actor did not reply")));
+            }
+        }
+        let _ = nix::unistd::write(self.waker.as_raw_fd(), &[0u8]);
+    }
+}
+
+/// Spawn `actor` in a new coroutine
+pub fn spawn_actor<A : Actor>(mioco : &MiocoHandle, actor : A) -> io::Result<Address<A::Message>> {
+    let eventfd = try!(EventFd::new(0));
+    let mailbox = Arc::new(Mailbox {
+        queue: Mutex::new(Queue {
+            messages: VecDeque::new(),
+            closed: false,
+        }),
+        notifier: try!(eventfd.notifier()),
+        senders: AtomicUsize::new(1),
+    });
+
+    {
+        let mailbox = mailbox.clone();
+        mioco.spawn(move |mioco| {
+            let res = run(mioco, actor, &mailbox, eventfd);
+
+            // drop pending messages, failing pending calls, outside the lock
+            let pending = {
+                let mut queue = mailbox.queue.lock().unwrap();
+                queue.closed = true;
+                mem::replace(&mut queue.messages, VecDeque::new())
+            };
+            drop(pending);
+
+            res
+        });
+    }
+
+    Ok(Address {
+        inn: mailbox,
+    })
+}
+
+fn run<A : Actor>(mioco : &mut MiocoHandle, mut actor : A, mailbox : &Mailbox<A::Message>, eventfd : EventFd) -> io::Result<()> {
+    let mut eventfd = mioco.wrap(eventfd);
+    try!(actor.started(mioco));
+
+    loop {
+        let no_senders = mailbox.senders.load(Ordering::SeqCst) == 0;
+
+        loop {
+            let msg = mailbox.queue.lock().unwrap().messages.pop_front();
+            match msg {
+                Some(msg) => try!(actor.handle(mioco, msg)),
+                None => break,
+            }
+        }
+
+        // Checked before draining the queue, so no message sent just
+        // before dropping the last `Address` is lost
+        if no_senders {
+            return Ok(());
+        }
+
+        try!(eventfd.wait());
+    }
+}
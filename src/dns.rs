@@ -0,0 +1,254 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! DNS resolution over UDP
+//!
+//! `MiocoHandle::resolve()` uses the system resolver in the thread pool.
+//! `Resolver` queries a given name server directly instead, blocking only
+//! the current coroutine, with no threads involved. It does not read
+//! `/etc/hosts` or apply search domains, so pass fully qualified names.
+//!
+//! ```ignore
+//! let resolver = Resolver::new(try!(FromStr::from_str("127.0.0.1:53")));
+//! let addrs = try!(resolver.lookup(mioco, "example.com", 80));
+//! ```
+
+use std::io;
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6, IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use super::{MiocoHandle, TypedEventSource};
+use super::net::UdpSocket;
+use super::sys;
+use super::timerfd::TimerFd;
+
+const TYPE_A : u16 = 1;
+const TYPE_AAAA : u16 = 28;
+const CLASS_IN : u16 = 1;
+
+const FLAG_RESPONSE : u16 = 0x8000;
+const FLAG_RECURSION_DESIRED : u16 = 0x0100;
+const RCODE_MASK : u16 = 0x000f;
+const RCODE_NXDOMAIN : u16 = 3;
+
+/// Size of the DNS message header
+const HEADER_SIZE : usize = 12;
+
+/// Maximum size of a DNS message over UDP
+const MAX_MESSAGE_SIZE : usize = 512;
+
+/// Resolver querying a single name server
+pub struct Resolver {
+    nameserver : SocketAddr,
+    timeout_ms : u64,
+}
+
+impl Resolver {
+    /// Create a resolver querying `nameserver`
+    ///
+    /// Lookups time out after 5 seconds by default.
+    pub fn new(nameserver : SocketAddr) -> Resolver {
+        Resolver {
+            nameserver: nameserver,
+            timeout_ms: 5000,
+        }
+    }
+
+    /// Fail lookups with `TimedOut` error after `timeout_ms` milliseconds
+    pub fn timeout(&mut self, timeout_ms : u64) -> &mut Resolver {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Resolve `host` to a list of socket addresses with `port`
+    ///
+    /// Both IPv4 and IPv6 addresses are queried, and IPv4 ones are returned
+    /// first. IP address literals are returned right away. Fails with
+    /// `NotFound` error if the host has no addresses.
+    pub fn lookup(&self, mioco : &mut MiocoHandle, host : &str, port : u16) -> io::Result<Vec<SocketAddr>> {
+        if let Ok(ip) = Ipv4Addr::from_str(host) {
+            return Ok(vec![SocketAddr::V4(SocketAddrV4::new(ip, port))]);
+        }
+        if let Ok(ip) = Ipv6Addr::from_str(host) {
+            return Ok(vec![SocketAddr::V6(SocketAddrV6::new(ip, port, 0, 0))]);
+        }
+
+        let bind = match self.nameserver {
+            SocketAddr::V4(..) => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0)),
+            SocketAddr::V6(..) => SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 0, 0, 0)),
+        };
+        let sock = try!(UdpSocket::bind(&bind));
+        try!(sock.connect(&self.nameserver));
+
+        let id = sys::precise_time_ns() as u16;
+        let mut pending = vec![(id, TYPE_A), (id.wrapping_add(1), TYPE_AAAA)];
+        for &(id, qtype) in &pending {
+            try!(sock.send(&try!(query(id, host, qtype))));
+        }
+
+        let timer = try!(TimerFd::new());
+        try!(timer.set_oneshot(self.timeout_ms));
+        let timer = mioco.wrap(timer);
+
+        let res = receive(mioco, &sock, &timer, &mut pending);
+        timer.release();
+        let answers = try!(res);
+
+        let mut addrs : Vec<SocketAddr> = answers.iter().filter_map(|ip| match *ip {
+            IpAddr::V4(ip) => Some(SocketAddr::V4(SocketAddrV4::new(ip, port))),
+            _ => None,
+        }).collect();
+        addrs.extend(answers.iter().filter_map(|ip| match *ip {
+            IpAddr::V6(ip) => Some(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, 0))),
+            _ => None,
+        }));
+
+        if addrs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("host {} not found", host)));
+        }
+
+        Ok(addrs)
+    }
+}
+
+/// Receive responses to `pending` queries, until all are answered or `timer` fires
+fn receive(mioco : &mut MiocoHandle, sock : &UdpSocket, timer : &TypedEventSource<TimerFd>,
+           pending : &mut Vec<(u16, u16)>) -> io::Result<Vec<IpAddr>> {
+    let mut answers = Vec::new();
+    let mut buf = [0u8; MAX_MESSAGE_SIZE];
+
+    while !pending.is_empty() {
+        // the socket is always writable, so wait only for something to read
+        let event = try!(mioco.select_read_from(&[sock.index(), timer.index()]));
+        if event.index() == timer.index() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "DNS lookup timed out"));
+        }
+
+        let size = try!(sock.recv(&mut buf));
+        let msg = &buf[..size];
+        if msg.len() < HEADER_SIZE {
+            continue;
+        }
+
+        // ignore stray datagrams, eg. late responses to previous queries
+        let id = read_u16(msg, 0);
+        let position = match pending.iter().position(|&(pending_id, _)| pending_id == id) {
+            Some(position) => position,
+            None => continue,
+        };
+        let flags = read_u16(msg, 2);
+        if flags & FLAG_RESPONSE == 0 {
+            continue;
+        }
+        pending.remove(position);
+
+        match flags & RCODE_MASK {
+            0 => try!(parse_answers(msg, &mut answers)),
+            RCODE_NXDOMAIN => {},
+            rcode => return Err(io::Error::new(io::ErrorKind::Other,
+                                               format!("DNS server failed with code {}", rcode))),
+        }
+    }
+
+    Ok(answers)
+}
+
+/// Build query for `host` records of `qtype`
+fn query(id : u16, host : &str, qtype : u16) -> io::Result<Vec<u8>> {
+    let mut msg = Vec::with_capacity(HEADER_SIZE + host.len() + 6);
+    for &field in &[id, FLAG_RECURSION_DESIRED, 1, 0, 0, 0] {
+        write_u16(&mut msg, field);
+    }
+
+    let name = host.trim_right_matches('.');
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid host name {}", host)));
+        }
+        msg.push(label.len() as u8);
+        msg.extend(label.as_bytes().iter().cloned());
+    }
+    msg.push(0);
+
+    write_u16(&mut msg, qtype);
+    write_u16(&mut msg, CLASS_IN);
+    Ok(msg)
+}
+
+/// Add A and AAAA records from the answer section of `msg` to `answers`
+///
+/// Other records (eg. `CNAME`) are skipped.
+fn parse_answers(msg : &[u8], answers : &mut Vec<IpAddr>) -> io::Result<()> {
+    let questions = read_u16(msg, 4);
+    let records = read_u16(msg, 6);
+    let mut pos = HEADER_SIZE;
+
+    for _ in 0..questions {
+        pos = try!(skip_name(msg, pos)) + 4;
+    }
+
+    for _ in 0..records {
+        pos = try!(skip_name(msg, pos));
+        if pos + 10 > msg.len() {
+            return Err(malformed());
+        }
+        let rtype = read_u16(msg, pos);
+        let class = read_u16(msg, pos + 2);
+        let len = read_u16(msg, pos + 8) as usize;
+        pos += 10;
+        if pos + len > msg.len() {
+            return Err(malformed());
+        }
+
+        let data = &msg[pos..pos + len];
+        match (rtype, class, len) {
+            (TYPE_A, CLASS_IN, 4) => {
+                answers.push(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])));
+            },
+            (TYPE_AAAA, CLASS_IN, 16) => {
+                let mut segments = [0u16; 8];
+                for (i, segment) in segments.iter_mut().enumerate() {
+                    *segment = read_u16(data, i * 2);
+                }
+                answers.push(IpAddr::V6(Ipv6Addr::new(segments[0], segments[1], segments[2], segments[3],
+                                                      segments[4], segments[5], segments[6], segments[7])));
+            },
+            _ => {},
+        }
+        pos += len;
+    }
+
+    Ok(())
+}
+
+/// Position right after the domain name starting at `pos`
+fn skip_name(msg : &[u8], mut pos : usize) -> io::Result<usize> {
+    loop {
+        let len = match msg.get(pos) {
+            Some(&len) => len as usize,
+            None => return Err(malformed()),
+        };
+
+        if len == 0 {
+            return Ok(pos + 1);
+        }
+        // compression pointer ends the name
+        if len & 0xc0 == 0xc0 {
+            return Ok(pos + 2);
+        }
+        pos += 1 + len;
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed DNS response")
+}
+
+fn read_u16(buf : &[u8], pos : usize) -> u16 {
+    (buf[pos] as u16) << 8 | buf[pos + 1] as u16
+}
+
+fn write_u16(buf : &mut Vec<u8>, val : u16) {
+    buf.push((val >> 8) as u8);
+    buf.push(val as u8);
+}
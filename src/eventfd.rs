@@ -0,0 +1,97 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Kernel counters usable as `mio` event sources
+//!
+//! `EventFd` becomes readable when its counter is non-zero. `Notifier`
+//! can increment it from any thread, so eg. a callback of a C library
+//! can wake up a coroutine blocked on `wait()`.
+
+use std::io;
+use std::sync::Arc;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+use libc;
+use mio;
+
+use super::{TypedEventSource, RW};
+use super::sys;
+
+/// Counter based on Linux `eventfd`
+pub struct EventFd {
+    io : mio::Io,
+}
+
+impl_evented!(EventFd, io);
+
+impl EventFd {
+    /// Create `EventFd` with counter set to `initial`
+    pub fn new(initial : u32) -> io::Result<EventFd> {
+        EventFd::new_impl(initial, 0)
+    }
+
+    /// Create `EventFd` in semaphore mode
+    ///
+    /// Every read decrements the counter by one, instead of resetting it.
+    pub fn semaphore(initial : u32) -> io::Result<EventFd> {
+        EventFd::new_impl(initial, libc::EFD_SEMAPHORE)
+    }
+
+    fn new_impl(initial : u32, flags : libc::c_int) -> io::Result<EventFd> {
+        let fd = try!(sys::cvt(unsafe {
+            sys::eventfd(initial as libc::c_uint, flags | libc::EFD_NONBLOCK | libc::EFD_CLOEXEC)
+        }));
+
+        Ok(EventFd {
+            io: unsafe { mio::Io::from_raw_fd(fd) },
+        })
+    }
+
+    /// Create `Notifier` incrementing the counter
+    pub fn notifier(&self) -> io::Result<Notifier> {
+        let fd = try!(sys::cvt(unsafe { libc::dup(self.io.as_raw_fd()) }));
+        let io = unsafe { mio::Io::from_raw_fd(fd) };
+        try!(sys::set_cloexec(fd));
+
+        Ok(Notifier {
+            io: Arc::new(io),
+        })
+    }
+
+    /// Read and reset the counter, or `None` if it's zero
+    pub fn try_read(&self) -> io::Result<Option<u64>> {
+        sys::read_u64(self.io.as_raw_fd())
+    }
+
+    /// Add `n` to the counter
+    pub fn notify(&self, n : u64) -> io::Result<()> {
+        notify(self.io.as_raw_fd(), n)
+    }
+}
+
+impl TypedEventSource<EventFd> {
+    /// Block until the counter is non-zero, then read and reset it
+    pub fn wait(&mut self) -> io::Result<u64> {
+        self.try_until(RW::Read, |eventfd| eventfd.try_read())
+    }
+}
+
+/// Handle incrementing counter of `EventFd` from any thread
+#[derive(Clone)]
+pub struct Notifier {
+    io : Arc<mio::Io>,
+}
+
+impl Notifier {
+    /// Add `n` to the counter
+    pub fn notify(&self, n : u64) -> io::Result<()> {
+        notify(self.io.as_raw_fd(), n)
+    }
+}
+
+fn notify(fd : RawFd, n : u64) -> io::Result<()> {
+    match try!(sys::write_u64(fd, n)) {
+        Some(()) => Ok(()),
+        None => Err(io::Error::new(io::ErrorKind::WouldBlock, "eventfd counter overflow")),
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Filesystem operations for `mioco` coroutines
+//!
+//! Regular files can't be handled by `mio`, so every operation is executed in
+//! a thread pool, while the calling coroutine is blocked cooperatively until it's
+//! finished.
+//!
+//! On Linux, changes in the filesystem can be watched with `Watcher`, which
+//! is a regular event source. `Tail` uses it to follow growing files.
+
+use std::cell::Cell;
+use std::fs;
+use std::io::{self, Read, Write, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::{MiocoHandle, Traffic, current_handle};
+use super::sys;
+use super::thread_pool::offload;
+
+#[cfg(target_os = "linux")]
+mod watch;
+
+#[cfg(target_os = "linux")]
+pub use self::watch::*;
+
+/// File opened in `mioco` coroutine
+///
+/// Implements standard library `Read`, `Write` and `Seek`, blocking only
+/// the coroutine that is using it. It can be handed over to another coroutine,
+/// as every operation blocks and is accounted to the coroutine executing it.
+///
+/// Operations panic when called outside of `mioco` coroutine.
+pub struct File {
+    inn : Arc<Mutex<fs::File>>,
+    traffic : Cell<Traffic>,
+}
+
+impl File {
+    /// Open a file in read-only mode
+    ///
+    /// See `std::fs::File::open()`.
+    pub fn open<P : AsRef<Path>>(mioco : &MiocoHandle, path : P) -> io::Result<File> {
+        let mut options = fs::OpenOptions::new();
+        options.read(true);
+        File::open_with(mioco, path, options)
+    }
+
+    /// Open a file in write-only mode, creating or truncating it
+    ///
+    /// See `std::fs::File::create()`.
+    pub fn create<P : AsRef<Path>>(mioco : &MiocoHandle, path : P) -> io::Result<File> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        File::open_with(mioco, path, options)
+    }
+
+    /// Open a file with custom `options`
+    pub fn open_with<P : AsRef<Path>>(mioco : &MiocoHandle, path : P, options : fs::OpenOptions) -> io::Result<File> {
+        let path = path.as_ref().to_path_buf();
+        let file = try!(offload(&mioco.coroutine, move || options.open(&path)));
+
+        Ok(File {
+            inn: Arc::new(Mutex::new(file)),
+            traffic: Cell::new(Traffic::default()),
+        })
+    }
+
+    /// Execute `f` on the underlying `std::fs::File` in the thread pool
+    fn offload<F, T>(&self, f : F) -> io::Result<T>
+        where F : FnOnce(&mut fs::File) -> io::Result<T> + Send + 'static,
+              T : Send + 'static {
+            let file = self.inn.clone();
+            let start_ns = sys::precise_time_ns();
+            let res = offload(&current_handle().coroutine, move || f(&mut *file.lock().unwrap()));
+
+            self.account(&Traffic {
+                waits: 1,
+                blocked_us: (sys::precise_time_ns() - start_ns) / 1000,
+                .. Traffic::default()
+            });
+            res
+        }
+
+    /// Add `traffic` to the file and the current coroutine
+    fn account(&self, traffic : &Traffic) {
+        let mut total = self.traffic.get();
+        total.add(traffic);
+        self.traffic.set(total);
+        current_handle().coroutine.borrow_mut().extra_traffic.add(traffic);
+    }
+
+    /// Traffic of the file since it was opened
+    ///
+    /// Every operation executed in the thread pool counts as a wait.
+    /// Included in `MiocoHandle::traffic()`.
+    pub fn traffic(&self) -> Traffic {
+        self.traffic.get()
+    }
+
+    /// Query metadata of the file
+    pub fn metadata(&self) -> io::Result<fs::Metadata> {
+        self.offload(|file| file.metadata())
+    }
+
+    /// Flush all data and metadata to the disk
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.offload(|file| file.sync_all())
+    }
+
+    /// Flush all data to the disk
+    pub fn sync_data(&self) -> io::Result<()> {
+        self.offload(|file| file.sync_data())
+    }
+
+    /// Truncate or extend the file to `size`
+    pub fn set_len(&self, size : u64) -> io::Result<()> {
+        self.offload(move |file| file.set_len(size))
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        let len = buf.len();
+        let data = try!(self.offload(move |file| {
+            let mut data = vec![0u8; len];
+            let size = try!(file.read(&mut data));
+            data.truncate(size);
+            Ok(data)
+        }));
+
+        for (dst, src) in buf.iter_mut().zip(data.iter()) {
+            *dst = *src;
+        }
+
+        self.account(&Traffic {
+            bytes_read: data.len() as u64,
+            .. Traffic::default()
+        });
+        Ok(data.len())
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        let data = buf.to_vec();
+        let size = try!(self.offload(move |file| file.write(&data)));
+
+        self.account(&Traffic {
+            bytes_written: size as u64,
+            .. Traffic::default()
+        });
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.offload(|file| file.flush())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos : SeekFrom) -> io::Result<u64> {
+        self.offload(move |file| file.seek(pos))
+    }
+}
+
+/// Query metadata of a path
+///
+/// See `std::fs::metadata()`.
+pub fn metadata<P : AsRef<Path>>(mioco : &MiocoHandle, path : P) -> io::Result<fs::Metadata> {
+    let path = path.as_ref().to_path_buf();
+    offload(&mioco.coroutine, move || fs::metadata(&path))
+}
+
+/// List entries of a directory
+///
+/// Unlike `std::fs::read_dir()` all the entries are read at once.
+pub fn read_dir<P : AsRef<Path>>(mioco : &MiocoHandle, path : P) -> io::Result<Vec<fs::DirEntry>> {
+    let path = path.as_ref().to_path_buf();
+    offload(&mioco.coroutine, move || {
+        fs::read_dir(&path).and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+    })
+}
@@ -0,0 +1,401 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! `inotify` based filesystem `Watcher`, and `Tail` built on it
+
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{CString, OsStr};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use libc;
+use mio;
+
+use super::{File, metadata};
+use super::super::{MiocoHandle, TypedEventSource, LazyEventSource, RW};
+use super::super::current_handle;
+use super::super::sys;
+use super::super::timerfd::TimerFd;
+
+/// Mask of `inotify` event types
+pub type WatchMask = u32;
+
+/// File was accessed
+pub const IN_ACCESS : WatchMask = 0x00000001;
+/// File was modified
+pub const IN_MODIFY : WatchMask = 0x00000002;
+/// Metadata changed
+pub const IN_ATTRIB : WatchMask = 0x00000004;
+/// File opened for writing was closed
+pub const IN_CLOSE_WRITE : WatchMask = 0x00000008;
+/// File not opened for writing was closed
+pub const IN_CLOSE_NOWRITE : WatchMask = 0x00000010;
+/// File was opened
+pub const IN_OPEN : WatchMask = 0x00000020;
+/// File was moved out of watched directory
+pub const IN_MOVED_FROM : WatchMask = 0x00000040;
+/// File was moved into watched directory
+pub const IN_MOVED_TO : WatchMask = 0x00000080;
+/// File was created in watched directory
+pub const IN_CREATE : WatchMask = 0x00000100;
+/// File was deleted from watched directory
+pub const IN_DELETE : WatchMask = 0x00000200;
+/// Watched file was deleted
+pub const IN_DELETE_SELF : WatchMask = 0x00000400;
+/// Watched file was moved
+pub const IN_MOVE_SELF : WatchMask = 0x00000800;
+/// All the events above
+pub const IN_ALL_EVENTS : WatchMask = 0x00000fff;
+
+const IN_Q_OVERFLOW : WatchMask = 0x00004000;
+const IN_IGNORED : WatchMask = 0x00008000;
+const IN_ISDIR : WatchMask = 0x40000000;
+
+/// Kind of a filesystem change
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// File was accessed
+    Access,
+    /// File was modified
+    Modify,
+    /// Metadata changed
+    Attrib,
+    /// File opened for writing was closed
+    CloseWrite,
+    /// File not opened for writing was closed
+    CloseNoWrite,
+    /// File was opened
+    Open,
+    /// File was moved out of watched directory
+    MovedFrom,
+    /// File was moved into watched directory
+    MovedTo,
+    /// File was created in watched directory
+    Create,
+    /// File was deleted from watched directory
+    Delete,
+    /// Watched file was deleted
+    DeleteSelf,
+    /// Watched file was moved
+    MoveSelf,
+    /// Watch was removed, explicitly or because the file is gone
+    Ignored,
+    /// Kernel event queue overflowed and some events were lost
+    Overflow,
+}
+
+impl EventKind {
+    fn from_mask(mask : WatchMask) -> Option<EventKind> {
+        let kinds = [
+            (IN_Q_OVERFLOW, EventKind::Overflow),
+            (IN_CREATE, EventKind::Create),
+            (IN_DELETE, EventKind::Delete),
+            (IN_MOVED_FROM, EventKind::MovedFrom),
+            (IN_MOVED_TO, EventKind::MovedTo),
+            (IN_MODIFY, EventKind::Modify),
+            (IN_ATTRIB, EventKind::Attrib),
+            (IN_CLOSE_WRITE, EventKind::CloseWrite),
+            (IN_CLOSE_NOWRITE, EventKind::CloseNoWrite),
+            (IN_OPEN, EventKind::Open),
+            (IN_ACCESS, EventKind::Access),
+            (IN_DELETE_SELF, EventKind::DeleteSelf),
+            (IN_MOVE_SELF, EventKind::MoveSelf),
+            (IN_IGNORED, EventKind::Ignored),
+        ];
+
+        kinds.iter().find(|&&(bit, _)| mask & bit != 0).map(|&(_, kind)| kind)
+    }
+}
+
+/// Filesystem change reported by `Watcher`
+#[derive(Clone, Debug)]
+pub struct Event {
+    /// Path of the changed file
+    ///
+    /// Watched path itself, or its entry for changes in a watched
+    /// directory. Empty for `EventKind::Overflow`.
+    pub path : PathBuf,
+    /// Kind of the change
+    pub kind : EventKind,
+    /// Is the changed file a directory
+    pub is_dir : bool,
+    /// Cookie pairing `MovedFrom` and `MovedTo` of a single rename
+    pub cookie : u32,
+}
+
+/// Filesystem change notifications based on Linux `inotify`
+///
+/// Add watches and `wrap` it in a coroutine. Then block on `next_event()`,
+/// or use it in `select`-like operations along with other event sources.
+/// Events are read in batches, so drain them with `try_next_event()`
+/// before blocking in `select` again.
+pub struct Watcher {
+    io : mio::Io,
+    paths : HashMap<libc::c_int, PathBuf>,
+    pending : VecDeque<Event>,
+}
+
+impl_evented!(Watcher, io);
+
+impl Watcher {
+    /// Create `Watcher` with no watches
+    pub fn new() -> io::Result<Watcher> {
+        let fd = try!(sys::cvt(unsafe {
+            sys::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC)
+        }));
+
+        Ok(Watcher {
+            io: unsafe { mio::Io::from_raw_fd(fd) },
+            paths: HashMap::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Watch `path` for changes in `mask`
+    ///
+    /// Watching the same path again replaces its mask.
+    pub fn add<P : AsRef<Path>>(&mut self, path : P, mask : WatchMask) -> io::Result<()> {
+        let path = path.as_ref();
+        let cpath = try!(CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "path contains NUL byte")
+        }));
+
+        let wd = try!(sys::cvt(unsafe {
+            sys::inotify_add_watch(self.io.as_raw_fd(), cpath.as_ptr(), mask)
+        }));
+        self.paths.insert(wd, path.to_path_buf());
+
+        Ok(())
+    }
+
+    /// Stop watching `path`
+    pub fn remove<P : AsRef<Path>>(&mut self, path : P) -> io::Result<()> {
+        let path = path.as_ref();
+        let wd = match self.paths.iter().find(|&(_, p)| p == path) {
+            Some((&wd, _)) => wd,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "path is not watched")),
+        };
+
+        try!(sys::cvt(unsafe { sys::inotify_rm_watch(self.io.as_raw_fd(), wd) }));
+
+        Ok(())
+    }
+
+    /// Get next pending event, or `None` if there's none
+    pub fn try_next_event(&mut self) -> io::Result<Option<Event>> {
+        if self.pending.is_empty() {
+            try!(self.fill());
+        }
+
+        Ok(self.pending.pop_front())
+    }
+
+    /// Read and parse available events into `pending`
+    fn fill(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 4096];
+        let size = match try!(sys::would_block(sys::cvt_size(unsafe {
+            libc::read(self.io.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void,
+                       buf.len() as libc::size_t)
+        }))) {
+            Some(size) => size,
+            None => return Ok(()),
+        };
+
+        let mut pos = 0;
+        while pos + sys::INOTIFY_EVENT_SIZE <= size {
+            // `struct inotify_event { int wd; uint32_t mask, cookie, len; char name[]; }`
+            let wd = read_u32(&buf[pos..]) as libc::c_int;
+            let mask = read_u32(&buf[pos + 4..]);
+            let cookie = read_u32(&buf[pos + 8..]);
+            let len = read_u32(&buf[pos + 12..]) as usize;
+            let name_start = pos + sys::INOTIFY_EVENT_SIZE;
+            let name = &buf[name_start..name_start + len];
+            pos = name_start + len;
+
+            let kind = match EventKind::from_mask(mask) {
+                Some(kind) => kind,
+                None => continue,
+            };
+
+            let mut path = self.paths.get(&wd).cloned().unwrap_or_else(PathBuf::new);
+            let name = match name.iter().position(|&b| b == 0) {
+                Some(end) => &name[..end],
+                None => name,
+            };
+            if !name.is_empty() {
+                path.push(OsStr::from_bytes(name));
+            }
+
+            if kind == EventKind::Ignored {
+                self.paths.remove(&wd);
+            }
+
+            self.pending.push_back(Event {
+                path: path,
+                kind: kind,
+                is_dir: mask & IN_ISDIR != 0,
+                cookie: cookie,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn read_u32(buf : &[u8]) -> u32 {
+    let mut val = 0u32;
+    unsafe {
+        ptr::copy_nonoverlapping(buf.as_ptr(), &mut val as *mut u32 as *mut u8, 4);
+    }
+    val
+}
+
+impl TypedEventSource<Watcher> {
+    /// Block on receiving next event
+    pub fn next_event(&mut self) -> io::Result<Event> {
+        self.try_until(RW::Read, |watcher| watcher.try_next_event())
+    }
+
+    /// Watch `path` for changes in `mask`
+    ///
+    /// See `Watcher::add()`.
+    pub fn add<P : AsRef<Path>>(&mut self, path : P, mask : WatchMask) -> io::Result<()> {
+        let path = path.as_ref();
+        self.with_raw_mut(|watcher| watcher.add(path, mask))
+    }
+
+    /// Stop watching `path`
+    pub fn remove<P : AsRef<Path>>(&mut self, path : P) -> io::Result<()> {
+        let path = path.as_ref();
+        self.with_raw_mut(|watcher| watcher.remove(path))
+    }
+}
+
+/// How often `Tail` checks the file if no change was reported
+const TAIL_POLL_MS : u64 = 1000;
+
+/// Follower of a growing file, like `tail -F`
+///
+/// Reading blocks the coroutine until new data is appended. If the file
+/// is truncated, reading starts again from the beginning. If it's rotated
+/// (another file appears under the path), the old one is read to the end
+/// and the new one is followed from the beginning.
+///
+/// Changes are detected with `inotify` on the parent directory, and by
+/// polling every second, for filesystems that don't report them.
+///
+/// Like `mioco::net` types, it registers itself in the coroutine that reads
+/// it, so it can be handed over to another one. Reading panics when called
+/// outside of `mioco` coroutine.
+pub struct Tail {
+    path : PathBuf,
+    file : File,
+    ino : u64,
+    pos : u64,
+    watcher : LazyEventSource<Watcher>,
+    timer : LazyEventSource<TimerFd>,
+}
+
+impl Tail {
+    /// Follow file at `path`, starting at `offset`
+    pub fn open<P : AsRef<Path>>(mioco : &MiocoHandle, path : P, offset : u64) -> io::Result<Tail> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = try!(File::open(mioco, &path));
+        let ino = try!(file.metadata()).ino();
+        let pos = try!(file.seek(SeekFrom::Start(offset)));
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let mut watcher = try!(Watcher::new());
+        try!(watcher.add(&dir, IN_MODIFY | IN_ATTRIB | IN_CREATE | IN_MOVED_TO));
+
+        Ok(Tail {
+            path: path,
+            file: file,
+            ino: ino,
+            pos: pos,
+            watcher: LazyEventSource::new(watcher),
+            timer: LazyEventSource::new(try!(TimerFd::new())),
+        })
+    }
+
+    /// Current offset in the followed file
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Switch to the beginning of a new or truncated file
+    ///
+    /// Returns `false` if there's nothing new to read.
+    fn reopen_if_changed(&mut self) -> io::Result<bool> {
+        let mioco = current_handle();
+        match metadata(&mioco, &self.path) {
+            Ok(ref meta) if meta.ino() != self.ino => {
+                let file = try!(File::open(&mioco, &self.path));
+                self.ino = try!(file.metadata()).ino();
+                self.file = file;
+                self.pos = 0;
+                return Ok(true);
+            },
+            Ok(_) => {},
+            // rotated away, and not created again yet
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        if try!(self.file.metadata()).len() < self.pos {
+            self.pos = try!(self.file.seek(SeekFrom::Start(0)));
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Block until the file might have changed
+    fn wait(&mut self) -> io::Result<()> {
+        let mut mioco = current_handle();
+        try!(self.timer.with_raw(|timer| timer.set_oneshot(TAIL_POLL_MS)));
+
+        loop {
+            while let Some(event) = try!(self.watcher.with_source(|source| {
+                source.with_raw_mut(|watcher| watcher.try_next_event())
+            })) {
+                if event.kind == EventKind::Overflow || event.path.file_name() == self.path.file_name() {
+                    return Ok(());
+                }
+            }
+
+            let timer = self.timer.index();
+            let event = try!(mioco.select_read_from(&[self.watcher.index(), timer]));
+            if event.index() == timer {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Read for Tail {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let size = try!(self.file.read(buf));
+            if size > 0 {
+                self.pos += size as u64;
+                return Ok(size);
+            }
+
+            if !try!(self.reopen_if_changed()) {
+                try!(self.wait());
+            }
+        }
+    }
+}
@@ -23,22 +23,67 @@
 #![feature(result_expect)]
 #![feature(reflect_marker)]
 #![feature(rc_weak)]
+#![feature(process_exec)]
+#![feature(ip_addr)]
 #![warn(missing_docs)]
 
 extern crate mio;
 extern crate coroutine;
 extern crate nix;
+extern crate libc;
 #[macro_use]
 extern crate log;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::mem;
 use std::rc::{Rc, Weak};
-use std::io;
+use std::sync::{Arc, Mutex};
+use std::io::{self, Read, Write};
+use std::error::Error;
+use std::fmt;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::unix::io::{RawFd, AsRawFd, FromRawFd};
 
 use mio::{TryRead, TryWrite, Token, Handler, EventLoop, EventSet};
 use std::any::Any;
 use std::marker::{PhantomData, Reflect};
 use mio::util::Slab;
+use mio::unix::{PipeReader, PipeWriter};
+
+#[macro_use]
+mod sys;
+mod thread_pool;
+pub mod fs;
+pub mod net;
+#[cfg(target_os = "linux")]
+pub mod dns;
+#[cfg(target_os = "linux")]
+pub mod unix;
+#[cfg(target_os = "linux")]
+pub mod signal;
+#[cfg(target_os = "linux")]
+pub mod process;
+pub mod stdio;
+#[cfg(target_os = "linux")]
+pub mod pty;
+#[cfg(target_os = "linux")]
+pub mod timerfd;
+#[cfg(target_os = "linux")]
+pub mod eventfd;
+pub mod supervisor;
+#[cfg(target_os = "linux")]
+pub mod actor;
+
+use thread_pool::{ThreadPool, Completion};
+#[cfg(target_os = "linux")]
+use signal::SignalFd;
+
+/// Signal source of `Server`, created only where `signal` module is available
+#[cfg(target_os = "linux")]
+type SignalSource = SignalFd;
+#[cfg(not(target_os = "linux"))]
+type SignalSource = ();
 
 /// Read/Write/Both
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -155,6 +200,12 @@ type RefCoroutine = Rc<RefCell<Coroutine>>;
 ///
 /// Referenced by EventSourceShared running within it.
 struct Coroutine {
+    /// Unique identifier, key in `ServerShared::coroutines`
+    id : usize,
+
+    /// Name given in `MiocoHandle::spawn_named()`
+    name : Option<String>,
+
     /// Coroutine of Coroutine itself. Stored here so it's available
     /// through every handle and `Coroutine` itself without referencing
     /// back
@@ -166,8 +217,10 @@ struct Coroutine {
     /// Last event that resumed the coroutine
     last_event: LastEvent,
 
-    /// All handles, weak to avoid `Rc`-cycle
-    io : Vec<Weak<RefCell<EventSourceShared>>>,
+    /// All handles, weak to avoid `Rc`-cycle, by their index
+    ///
+    /// Slots of released sources are empty, and reused by new ones.
+    io : Vec<Option<Weak<RefCell<EventSourceShared>>>>,
 
     /// Mask of handle indexes that we're blocked on
     blocked_on_mask : u32,
@@ -175,61 +228,131 @@ struct Coroutine {
     /// Mask of handle indexes that are registered in Server
     registered_mask : u32,
 
+    /// When the `Coroutine` blocked last time, from `sys::precise_time_ns()`
+    blocked_since_ns : u64,
+
     /// `Server` shared data that this `Coroutine` is running in
     server_shared : RefServerShared,
 
     /// Newly spawned `Coroutine`-es
     children_to_start : Vec<RefCoroutine>,
+
+    /// Pipe used to wake up after a job in `ThreadPool` is done. Created on first use.
+    completion : Option<Completion>,
+
+    /// Blocked in `accept`, to be interrupted on shutdown
+    accepting : bool,
+
+    /// Error to return from the blocking operation the `Coroutine` was woken up from
+    interrupted : Option<io::Error>,
+
+    /// Cancelled with `JoinHandle::cancel()`; blocking operations fail from now on
+    cancelled : bool,
+
+    /// Value returned by the `Coroutine` function
+    result : Option<io::Result<()>>,
+
+    /// Notified when the `Coroutine` finishes, eg. by the `Scope` of the parent
+    join_waker : Option<Arc<PipeWriter>>,
+
+    /// Traffic of released sources and of `fs::File`-s, not held in `io`
+    extra_traffic : Traffic,
 }
 
 
 impl Coroutine {
-    fn new(server : RefServerShared) -> Self {
+    fn new(server : RefServerShared, id : usize, name : Option<String>) -> Self {
         Coroutine {
+            id: id,
+            name: name,
             state: State::Running,
             handle: None,
             last_event: LastEvent{ rw: RW::Read, index: EventSourceIndex(0)},
             io: Vec::with_capacity(4),
             blocked_on_mask: 0,
             registered_mask: 0,
+            blocked_since_ns: 0,
             server_shared: server,
             children_to_start: Vec::new(),
+            completion: None,
+            accepting: false,
+            interrupted: None,
+            cancelled: false,
+            result: None,
+            join_waker: None,
+            extra_traffic: Traffic::default(),
+        }
+    }
+
+    /// Id and name, for logging
+    fn label(&self) -> String {
+        match self.name {
+            Some(ref name) => format!("{} ({})", CoroutineId(self.id), name),
+            None => CoroutineId(self.id).to_string(),
+        }
+    }
+
+    /// Prepare for blocking, failing if the `Coroutine` was cancelled
+    fn before_block(&mut self) -> io::Result<()> {
+        if self.cancelled {
+            Err(cancelled_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check if the `Coroutine` was interrupted while blocked
+    fn after_block(&mut self) -> io::Result<()> {
+        match self.interrupted.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Resume `coroutine`, marking it finished if it panicked
+    fn resume(coroutine : &RefCoroutine, caller : &str) {
+        let handle = {
+            let co = coroutine.borrow();
+            co.server_shared.borrow_mut().stats.resumes += 1;
+            co.handle.as_ref().map(|c| c.clone()).unwrap()
+        };
+
+        if let Err(reason) = handle.resume() {
+            error!("Coroutine {}: resume failed: {:?} in {}", coroutine.borrow().label(), reason, caller);
+            let mut co = coroutine.borrow_mut();
+            co.state = State::Finished;
+            co.blocked_on_mask = 0;
         }
     }
 
     /// After `resume()` on the `Coroutine.handle` finished,
     /// the `Coroutine` have blocked or finished and we need to
     /// perform the following maintenance
-    fn after_resume(&mut self, event_loop: &mut EventLoop<Server>) {
+    ///
+    /// Takes `RefCoroutine`, so it's not borrowed while children are running
+    /// (they might eg. cancel it).
+    fn after_resume(coroutine : &RefCoroutine, event_loop: &mut EventLoop<Server>) {
         // If there were any newly spawned child-coroutines: start them now
-        for coroutine in &self.children_to_start {
-            let handle = {
-                let co = coroutine.borrow_mut();
-                co.handle.as_ref().map(|c| c.clone()).unwrap()
-            };
-            trace!("Resume new child coroutine");
-            {
-                if let Err(reason) = handle.resume() {
-                    error!("Co resume failed: {:?} in after_resume()", reason);
-                    let mut co = coroutine.borrow_mut();
-                    co.state = State::Finished;
-                    co.blocked_on_mask = 0;
-                } else {
-                    coroutine.borrow_mut().reregister(event_loop);
-                }
-            }
+        let children_to_start = mem::replace(&mut coroutine.borrow_mut().children_to_start, Vec::new());
+        for coroutine in &children_to_start {
+            trace!("Coroutine {}: resume new child", coroutine.borrow().label());
+            Coroutine::resume(coroutine, "after_resume()");
+            coroutine.borrow_mut().reregister(event_loop);
         }
-        self.children_to_start.clear();
 
         trace!("Reregister coroutine");
-        self.reregister(event_loop);
+        coroutine.borrow_mut().reregister(event_loop);
     }
 
     fn reregister(&mut self, event_loop: &mut EventLoop<Server>) {
         if self.state == State::Finished {
-            debug!("Coroutine: deregistering");
+            debug!("Coroutine {}: deregistering", self.label());
             self.deregister_all(event_loop);
+            if let Some(waker) = self.join_waker.take() {
+                let _ = nix::unistd::write(waker.as_raw_fd(), &[0u8]);
+            }
             let mut shared = self.server_shared.borrow_mut();
+            shared.coroutines.remove(&self.id);
             shared.coroutines_no -= 1;
             if shared.coroutines_no == 0 {
                 debug!("Shutdown event loop - 0 coroutines left");
@@ -244,12 +367,92 @@ impl Coroutine {
         let mut shared = self.server_shared.borrow_mut();
 
         for i in 0..self.io.len() {
-            let io = self.io[i].upgrade().unwrap();
+            let io = match self.source(i) {
+                Some(io) => io,
+                None => continue,
+            };
             let mut io = io.borrow_mut();
+            if io.lazy {
+                io.orphaned = true;
+            } else {
+                io.released = true;
+            }
             io.deregister(event_loop);
             trace!("Removing source token={:?}", io.token);
             shared.sources.remove(io.token).expect("cleared empty slot");
+            shared.stats.sources -= 1;
+        }
+    }
+
+    /// Source at `index`, if it was not released
+    fn source(&self, index : usize) -> Option<RefEventSourceShared> {
+        self.io.get(index).and_then(|io| io.as_ref()).map(|io| io.upgrade().unwrap())
+    }
+
+    /// Index of a free slot for a new source
+    ///
+    /// Panics if all the slots are taken, as masks can't hold more.
+    fn free_index(&self) -> usize {
+        match self.io.iter().position(|io| io.is_none()) {
+            Some(index) => index,
+            None if self.io.len() < MAX_COROUTINE_SOURCES => self.io.len(),
+            None => panic!("Coroutine {}: more than {} event sources", self.label(), MAX_COROUTINE_SOURCES),
+        }
+    }
+
+    /// Put `io` in a slot returned by `free_index()`
+    fn set_source(&mut self, index : usize, io : Weak<RefCell<EventSourceShared>>) {
+        if index == self.io.len() {
+            self.io.push(Some(io));
+        } else {
+            debug_assert!(self.io[index].is_none());
+            self.io[index] = Some(io);
+        }
+    }
+
+    /// Is the `Coroutine` blocked on a listening socket, eg. in `select()`
+    fn blocked_on_listener(&self) -> bool {
+        let mask = match self.state {
+            State::BlockedOn(_) => self.blocked_on_mask | self.registered_mask,
+            _ => return false,
+        };
+
+        (0..self.io.len()).filter(|&i| mask & (1 << i) != 0)
+            .filter_map(|i| self.source(i))
+            .any(|io| io.borrow().listen_fd.is_some())
+    }
+
+    /// Shut down all the listening sockets of the `Coroutine`
+    fn shutdown_listeners(&self) {
+        for io in (0..self.io.len()).filter_map(|i| self.source(i)) {
+            if let Some(fd) = io.borrow().listen_fd {
+                shutdown_listener(fd);
+            }
+        }
+    }
+
+    /// Free the slot of a source that was released
+    fn remove_source(&mut self, index : usize) {
+        self.io[index] = None;
+        self.blocked_on_mask &= !(1 << index);
+        self.registered_mask &= !(1 << index);
+    }
+
+    /// Wake up `coroutine` blocked on its sources, making the blocking
+    /// operation return `err`
+    fn interrupt(coroutine : &RefCoroutine, event_loop : &mut EventLoop<Server>, err : io::Error) {
+        {
+            let mut co = coroutine.borrow_mut();
+            match co.state {
+                State::BlockedOn(_) => {},
+                _ => return,
+            }
+            co.state = State::Running;
+            co.interrupted = Some(err);
         }
+
+        Coroutine::resume(coroutine, "interrupt()");
+        Coroutine::after_resume(coroutine, event_loop);
     }
 
     fn reregister_blocked_on(&mut self, event_loop: &mut EventLoop<Server>) {
@@ -261,12 +464,15 @@ impl Coroutine {
 
         // TODO: count leading zeros + for i in 0..32 {
         for i in 0..self.io.len() {
+            let io = match self.source(i) {
+                Some(io) => io,
+                None => continue,
+            };
+
             if (self.blocked_on_mask & (1 << i)) != 0 {
-                let io = self.io[i].upgrade().unwrap();
                 let mut io = io.borrow_mut();
                 io.reregister(event_loop, rw);
             } else if (self.registered_mask & (1 << i)) != 0 {
-                let io = self.io[i].upgrade().unwrap();
                 let io = io.borrow();
                 io.unreregister(event_loop);
             }
@@ -274,6 +480,152 @@ impl Coroutine {
 
         self.registered_mask = self.blocked_on_mask;
         self.blocked_on_mask = 0;
+        self.blocked_since_ns = sys::precise_time_ns();
+    }
+
+    /// Describe the `Coroutine` for `MiocoHandle::dump()`
+    fn info(&self, now_ns : u64) -> CoroutineInfo {
+        let (state, blocked_on, blocked_ms) = match self.state {
+            State::BlockedOn(rw) => {
+                let blocked_on = (0..self.io.len())
+                    .filter(|&i| self.registered_mask & (1 << i) != 0)
+                    .filter_map(|i| self.source(i).map(|io| SourceInfo {
+                        index: EventSourceIndex(i),
+                        token: io.borrow().token.as_usize(),
+                    }))
+                    .collect();
+                let blocked_ms = now_ns.saturating_sub(self.blocked_since_ns) / 1000_000;
+                (CoroutineState::BlockedOn(rw), blocked_on, Some(blocked_ms))
+            },
+            State::Running => (CoroutineState::Running, Vec::new(), None),
+            State::Finished => (CoroutineState::Finished, Vec::new(), None),
+        };
+
+        CoroutineInfo {
+            id: CoroutineId(self.id),
+            name: self.name.clone(),
+            state: state,
+            blocked_on: blocked_on,
+            blocked_ms: blocked_ms,
+            cancelled: self.cancelled,
+            traffic: self.traffic(),
+        }
+    }
+
+    /// Sum of `Traffic` of all the sources of the coroutine, except internal ones
+    fn traffic(&self) -> Traffic {
+        let mut traffic = self.extra_traffic;
+        for i in 0..self.io.len() {
+            if let Some(io) = self.source(i) {
+                traffic.add(&io.borrow().unaccounted_traffic());
+            }
+        }
+        traffic
+    }
+}
+
+/// State of a coroutine, as reported by `MiocoHandle::dump()`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CoroutineState {
+    /// Running, or ready to run
+    Running,
+    /// Blocked waiting for events
+    BlockedOn(RW),
+    /// Finished, but not cleaned up yet
+    Finished,
+}
+
+/// Event source a coroutine is blocked on
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SourceInfo {
+    /// Index of the source in its coroutine
+    pub index : EventSourceIndex,
+    /// `mio` token of the source
+    pub token : usize,
+}
+
+/// Snapshot of a coroutine, returned by `MiocoHandle::dump()`
+#[derive(Clone, Debug)]
+pub struct CoroutineInfo {
+    /// Id of the coroutine
+    pub id : CoroutineId,
+    /// Name of the coroutine, if it was given one
+    pub name : Option<String>,
+    /// Current state
+    pub state : CoroutineState,
+    /// Sources the coroutine is blocked on
+    pub blocked_on : Vec<SourceInfo>,
+    /// How long the coroutine is blocked, in milliseconds
+    pub blocked_ms : Option<u64>,
+    /// Was the coroutine cancelled
+    pub cancelled : bool,
+    /// Traffic of all the sources of the coroutine
+    pub traffic : Traffic,
+}
+
+impl fmt::Display for CoroutineInfo {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "coroutine {}", self.id));
+        if let Some(ref name) = self.name {
+            try!(write!(f, " ({})", name));
+        }
+
+        match self.state {
+            CoroutineState::BlockedOn(rw) => {
+                try!(write!(f, " [blocked on {:?}", rw));
+                if let Some(ms) = self.blocked_ms {
+                    try!(write!(f, ", {} ms", ms));
+                }
+                try!(write!(f, "]:"));
+                for source in &self.blocked_on {
+                    try!(write!(f, " {}/token={}", source.index.as_usize(), source.token));
+                }
+            },
+            CoroutineState::Running => try!(write!(f, " [running]")),
+            CoroutineState::Finished => try!(write!(f, " [finished]")),
+        }
+
+        if self.cancelled {
+            try!(write!(f, " (cancelled)"));
+        }
+
+        write!(f, " read={}B written={}B", self.traffic.bytes_read, self.traffic.bytes_written)
+    }
+}
+
+/// Traffic accounting of an event source, a file, or a whole coroutine
+///
+/// See `TypedEventSource::traffic()`, `fs::File::traffic()` and `MiocoHandle::traffic()`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Traffic {
+    /// Bytes read
+    pub bytes_read : u64,
+    /// Bytes written
+    pub bytes_written : u64,
+    /// Number of times the coroutine blocked waiting for the source
+    ///
+    /// `select`-like operations are not accounted to any source.
+    pub waits : u64,
+    /// Time spent blocked waiting for the source, in microseconds
+    pub blocked_us : u64,
+}
+
+impl Traffic {
+    fn add(&mut self, other : &Traffic) {
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+        self.waits += other.waits;
+        self.blocked_us += other.blocked_us;
+    }
+
+    /// Traffic on top of `base`
+    fn since(&self, base : &Traffic) -> Traffic {
+        Traffic {
+            bytes_read: self.bytes_read - base.bytes_read,
+            bytes_written: self.bytes_written - base.bytes_written,
+            waits: self.waits - base.waits,
+            blocked_us: self.blocked_us - base.blocked_us,
+        }
     }
 }
 
@@ -289,9 +641,31 @@ struct EventSourceShared {
     io : Box<Evented+'static>,
     peer_hup: bool,
     registered: bool,
+    traffic: Traffic,
+    /// Part of `traffic` accounted to coroutines the source was handed over from
+    accounted_traffic: Traffic,
+    /// Used by `mioco` itself, eg. `thread_pool::completion()`; hidden from `select()`
+    internal: bool,
+    /// Removed from its `Coroutine`, and to be removed from `Server`
+    released: bool,
+    /// Owned by `LazyEventSource`, so it can be handed over to another `Coroutine`
+    lazy: bool,
+    /// Its `Coroutine` finished before handing it over; not in `Server` anymore
+    orphaned: bool,
+    /// Listening socket, shut down when `mioco` shutdown starts
+    listen_fd: Option<RawFd>,
 }
 
 impl EventSourceShared {
+    /// Traffic to account to the current `Coroutine`
+    fn unaccounted_traffic(&self) -> Traffic {
+        if self.internal {
+            Traffic::default()
+        } else {
+            self.traffic.since(&self.accounted_traffic)
+        }
+    }
+
     /// Handle `hup` condition
     fn hup(&mut self, _event_loop: &mut EventLoop<Server>, _token: Token) {
             self.peer_hup = true;
@@ -370,32 +744,82 @@ impl<T> TypedEventSource<T>
 where T : Reflect+'static {
     /// Mark the `EventSource` blocked and block until `Server` does
     /// not wake us up again.
-    fn block_on(&self, rw : RW) {
+    ///
+    /// Fails if the `Coroutine` was interrupted instead.
+    fn block_on(&self, rw : RW) -> io::Result<()> {
         {
             let inn = self.inn.borrow();
+            if inn.released {
+                return Err(io::Error::new(io::ErrorKind::Other, "event source was released"));
+            }
+            try!(inn.coroutine.borrow_mut().before_block());
             inn.coroutine.borrow_mut().state = State::BlockedOn(rw);
             inn.coroutine.borrow_mut().blocked_on_mask = 1 << inn.index;
         }
-        trace!("coroutine blocked on {:?}", rw);
+        trace!("Coroutine {}: blocked on {:?}", self.inn.borrow().coroutine.borrow().label(), rw);
+        let start_ns = sys::precise_time_ns();
         coroutine::Coroutine::block();
         {
-            let inn = self.inn.borrow_mut();
+            let mut inn = self.inn.borrow_mut();
+            inn.traffic.waits += 1;
+            inn.traffic.blocked_us += (sys::precise_time_ns() - start_ns) / 1000;
+            set_current(Some(inn.coroutine.clone()));
+            try!(inn.coroutine.borrow_mut().after_block());
             debug_assert!(rw.has_read() || inn.coroutine.borrow().last_event.has_write());
             debug_assert!(rw.has_write() || inn.coroutine.borrow().last_event.has_read());
             debug_assert!(inn.coroutine.borrow().last_event.index().as_usize() == inn.index);
         }
+        Ok(())
+    }
+
+    /// Perform non-blocking operation `f` on raw mio type, blocking
+    /// on `rw` until it stops returning `Ok(None)`
+    fn try_until<F, R>(&self, rw : RW, mut f : F) -> io::Result<R>
+        where F : FnMut(&mut T) -> io::Result<Option<R>> {
+        loop {
+            let res = {
+                let mut inn = self.inn.borrow_mut();
+                f(inn.io.as_any_mut().downcast_mut::<T>().unwrap())
+            };
+
+            match res {
+                Ok(None) => {
+                    try!(self.block_on(rw))
+                },
+                Ok(Some(r)) => {
+                    return Ok(r);
+                },
+                Err(e) => {
+                    return Err(e)
+                }
+            }
+        }
+    }
+
+    /// Like `try_until(RW::Read, f)`, but fails once `mioco` is shutting down
+    fn accept_until<F, R>(&self, f : F) -> io::Result<R>
+        where F : FnMut(&mut T) -> io::Result<Option<R>> {
+        let coroutine = self.inn.borrow().coroutine.clone();
+        if coroutine.borrow().server_shared.borrow().shutting_down {
+            return Err(shutdown_error());
+        }
+
+        coroutine.borrow_mut().accepting = true;
+        let res = self.try_until(RW::Read, f);
+        coroutine.borrow_mut().accepting = false;
+        res
     }
 
     /// Access raw mio type
-    pub fn with_raw<F>(&self, f : F)
-        where F : Fn(&T) {
+    pub fn with_raw<F, R>(&self, f : F) -> R
+        where F : Fn(&T) -> R {
         let io = &self.inn.borrow().io;
         f(io.as_any().downcast_ref::<T>().unwrap())
     }
 
     /// Access mutable raw mio type
-    pub fn with_raw_mut<F>(&mut self, f : F)
-        where F : Fn(&mut T) {
+    pub fn with_raw_mut<F, R>(&mut self, f : F) -> R
+        where F : Fn(&mut T) -> R {
         let mut io = &mut self.inn.borrow_mut().io;
         f(io.as_any_mut().downcast_mut::<T>().unwrap())
     }
@@ -404,6 +828,242 @@ where T : Reflect+'static {
     pub fn index(&self) -> EventSourceIndex {
         EventSourceIndex(self.inn.borrow().index)
     }
+
+    /// Traffic of this source since it was wrapped
+    ///
+    /// Bytes are accounted by `mioco` blocking operations only, not by
+    /// direct access to the raw mio type.
+    pub fn traffic(&self) -> Traffic {
+        self.inn.borrow().traffic
+    }
+
+    /// Account `bytes` read from the source
+    fn count_read(&self, bytes : usize) {
+        self.inn.borrow_mut().traffic.bytes_read += bytes as u64;
+    }
+
+    /// Account `bytes` written to the source
+    fn count_written(&self, bytes : usize) {
+        self.inn.borrow_mut().traffic.bytes_written += bytes as u64;
+    }
+}
+
+impl<T> TypedEventSource<T> {
+    /// Remove the source from its coroutine, so its slot can be reused
+    ///
+    /// `Server` deregisters it and drops the raw mio type. Blocking on the
+    /// source fails from now on.
+    fn release(&self) {
+        let (coroutine, index, traffic) = {
+            let mut inn = self.inn.borrow_mut();
+            if inn.released {
+                return;
+            }
+            inn.released = true;
+            if inn.orphaned {
+                return;
+            }
+            (inn.coroutine.clone(), inn.index, inn.unaccounted_traffic())
+        };
+
+        let mut co = coroutine.borrow_mut();
+        trace!("Coroutine {}: releasing source {}", co.label(), index);
+        co.remove_source(index);
+        co.extra_traffic.add(&traffic);
+        co.server_shared.borrow_mut().to_release.push(self.inn.clone());
+    }
+
+    /// Move the source to `coroutine`, so it's the one woken up by its events
+    ///
+    /// Fails if the source was released, or another coroutine is blocked on it.
+    /// Orphaned source is added back to `Server`. Traffic so far stays accounted
+    /// to the previous coroutine.
+    fn move_to(&self, coroutine : &RefCoroutine) -> io::Result<()> {
+        let (old, index, registered, orphaned) = {
+            let inn = self.inn.borrow();
+            if inn.released {
+                return Err(io::Error::new(io::ErrorKind::Other, "event source was released"));
+            }
+            if inn.coroutine.borrow().id == coroutine.borrow().id {
+                return Ok(());
+            }
+            (inn.coroutine.clone(), inn.index, inn.registered, inn.orphaned)
+        };
+
+        if orphaned {
+            let co = coroutine.borrow();
+            let mut shared = co.server_shared.borrow_mut();
+            let inn = self.inn.clone();
+            let token = shared.sources.insert_with(|_| EventSource { inn: inn }).expect("run out of tokens");
+            shared.stats.sources += 1;
+            trace!("Added orphaned source token={:?}", token);
+            let mut inn = self.inn.borrow_mut();
+            inn.token = token;
+            inn.orphaned = false;
+        } else {
+            let mut old = old.borrow_mut();
+            let blocked_on = match old.state {
+                State::BlockedOn(_) => (old.blocked_on_mask | old.registered_mask) & (1 << index) != 0,
+                _ => false,
+            };
+            if blocked_on {
+                return Err(io::Error::new(io::ErrorKind::Other, "event source is in use by another coroutine"));
+            }
+            trace!("Coroutine {}: handing over source {}", old.label(), index);
+            old.remove_source(index);
+            old.extra_traffic.add(&self.inn.borrow().unaccounted_traffic());
+        }
+
+        let new_index = coroutine.borrow().free_index();
+        {
+            let mut co = coroutine.borrow_mut();
+            co.set_source(new_index, self.inn.clone().downgrade());
+            if registered {
+                co.registered_mask |= 1 << new_index;
+            }
+        }
+
+        let mut inn = self.inn.borrow_mut();
+        inn.coroutine = coroutine.clone();
+        inn.index = new_index;
+        inn.accounted_traffic = inn.traffic;
+        Ok(())
+    }
+}
+
+/// `mio` IO registered in the coroutine that uses it first
+///
+/// Allows creating IO in one coroutine and handing it over to another
+/// (eg. accepted connection to a newly spawned coroutine). The source moves
+/// to whichever coroutine uses it, even after the previous one finished.
+struct LazyEventSource<T> {
+    inn : RefCell<LazyState<T>>,
+    /// Listening socket, see `EventSourceShared::listen_fd`
+    listen_fd : Option<RawFd>,
+}
+
+enum LazyState<T> {
+    Raw(T),
+    Wrapped(TypedEventSource<T>),
+    /// Transitional state while wrapping
+    Empty,
+}
+
+impl<T> LazyEventSource<T>
+where T : Evented+Reflect+'static {
+    fn new(io : T) -> Self {
+        LazyEventSource {
+            inn: RefCell::new(LazyState::Raw(io)),
+            listen_fd: None,
+        }
+    }
+
+    /// Like `new()`, for a listening socket to be shut down with `mioco`
+    fn listener(io : T) -> Self
+        where T : AsRawFd {
+        let fd = io.as_raw_fd();
+        LazyEventSource {
+            inn: RefCell::new(LazyState::Raw(io)),
+            listen_fd: Some(fd),
+        }
+    }
+
+    fn from_wrapped(io : TypedEventSource<T>) -> Self {
+        io.inn.borrow_mut().lazy = true;
+        LazyEventSource {
+            inn: RefCell::new(LazyState::Wrapped(io)),
+            listen_fd: None,
+        }
+    }
+
+    /// `TypedEventSource` registered in the current coroutine
+    ///
+    /// Wraps the raw mio type on first use, and moves the source over if it
+    /// was used by another coroutine before. Fails if that coroutine is still
+    /// blocked on it.
+    fn source(&self) -> io::Result<TypedEventSource<T>> {
+        let mut state = self.inn.borrow_mut();
+        let mioco = current_handle();
+
+        let is_raw = match *state {
+            LazyState::Raw(_) => true,
+            _ => false,
+        };
+
+        if is_raw {
+            let io = match mem::replace(&mut *state, LazyState::Empty) {
+                LazyState::Raw(io) => io,
+                _ => unreachable!(),
+            };
+            let source = wrap_impl(&mioco.coroutine, io);
+            {
+                let mut inn = source.inn.borrow_mut();
+                inn.lazy = true;
+                inn.listen_fd = self.listen_fd;
+            }
+            // missed by `Server::shutdown()`
+            if let Some(fd) = self.listen_fd {
+                if mioco.coroutine.borrow().server_shared.borrow().shutting_down {
+                    shutdown_listener(fd);
+                }
+            }
+            *state = LazyState::Wrapped(source);
+        }
+
+        match *state {
+            LazyState::Wrapped(ref source) => {
+                try!(source.move_to(&mioco.coroutine));
+                Ok(TypedEventSource {
+                    inn: source.inn.clone(),
+                    _t: PhantomData,
+                })
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// Perform `f` on `TypedEventSource` registered in the current coroutine
+    ///
+    /// `self` is not borrowed while `f` blocks, so the same IO can be used by
+    /// other coroutines in the meantime. See `source()`.
+    fn with_source<F, R>(&self, f : F) -> io::Result<R>
+        where F : FnOnce(&mut TypedEventSource<T>) -> io::Result<R> {
+        let mut source = try!(self.source());
+        f(&mut source)
+    }
+
+    /// Index identificator of the source, registered in the current coroutine
+    ///
+    /// Panics if another coroutine is blocked on the source.
+    fn index(&self) -> EventSourceIndex {
+        self.source().expect("event source not available in this coroutine").index()
+    }
+
+    /// Access raw mio type, without registering it
+    fn with_raw<F, R>(&self, f : F) -> R
+        where F : Fn(&T) -> R {
+        match *self.inn.borrow() {
+            LazyState::Raw(ref io) => f(io),
+            LazyState::Wrapped(ref source) => source.with_raw(f),
+            LazyState::Empty => unreachable!(),
+        }
+    }
+
+    /// Traffic of the source, without registering it
+    fn traffic(&self) -> Traffic {
+        match *self.inn.borrow() {
+            LazyState::Wrapped(ref source) => source.traffic(),
+            _ => Traffic::default(),
+        }
+    }
+}
+
+impl<T> Drop for LazyEventSource<T> {
+    fn drop(&mut self) {
+        if let LazyState::Wrapped(ref source) = *self.inn.borrow() {
+            source.release();
+        }
+    }
 }
 
 impl EventSource {
@@ -432,58 +1092,28 @@ impl EventSource {
             index
         };
 
-        let handle = {
+        let coroutine = {
             let inn = self.inn.borrow();
-            let coroutine_handle = inn.coroutine.borrow().handle.as_ref().map(|c| c.clone()).unwrap();
             inn.coroutine.borrow_mut().state = State::Running;
             inn.coroutine.borrow_mut().last_event = LastEvent {
                 rw: event,
                 index: EventSourceIndex(my_index),
             };
-            coroutine_handle
-        };
-
-
-        if let Err(reason) = handle.resume() {
-            error!("Co resume failed in ready(): {:?}", reason);
-            let inn = self.inn.borrow();
-            inn.coroutine.borrow_mut().state = State::Finished;
-            inn.coroutine.borrow_mut().blocked_on_mask = 0;
-        }
-
-        let coroutine = {
-            let inn = &self.inn.borrow();
             inn.coroutine.clone()
         };
 
-        let mut co = coroutine.borrow_mut();
-
-        co.after_resume(event_loop);
+        Coroutine::resume(&coroutine, "ready()");
+        Coroutine::after_resume(&coroutine, event_loop);
     }
 }
 
 impl<T> TypedEventSource<T>
 where T : mio::TryAccept+Reflect+'static {
     /// Block on accept
+    ///
+    /// Fails once `mioco` is shutting down.
     pub fn accept(&self) -> io::Result<T::Output> {
-        loop {
-            let res = {
-                let mut inn = self.inn.borrow_mut();
-                inn.io.as_any_mut().downcast_mut::<T>().unwrap().accept()
-            };
-
-            match res {
-                Ok(None) => {
-                    self.block_on(RW::Read)
-                },
-                Ok(Some(r))  => {
-                    return Ok(r);
-                },
-                Err(e) => {
-                    return Err(e)
-                }
-            }
-        }
+        self.accept_until(|io| io.accept())
     }
 }
 
@@ -491,24 +1121,9 @@ impl<T> std::io::Read for TypedEventSource<T>
 where T : TryRead+Reflect+'static {
     /// Block on read
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        loop {
-            let res = {
-                let mut inn = self.inn.borrow_mut();
-                inn.io.as_any_mut().downcast_mut::<T>().unwrap().try_read(buf)
-            };
-
-            match res {
-                Ok(None) => {
-                    self.block_on(RW::Read)
-                },
-                Ok(Some(r))  => {
-                    return Ok(r);
-                },
-                Err(e) => {
-                    return Err(e)
-                }
-            }
-        }
+        let size = try!(self.try_until(RW::Read, |io| io.try_read(buf)));
+        self.count_read(size);
+        Ok(size)
     }
 }
 
@@ -516,24 +1131,9 @@ impl<T> std::io::Write for TypedEventSource<T>
 where T : TryWrite+Reflect+'static {
     /// Block on write
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        loop {
-            let res = {
-                let mut inn = self.inn.borrow_mut();
-                inn.io.as_any_mut().downcast_mut::<T>().unwrap().try_write(buf)
-            };
-
-            match res {
-                Ok(None) => {
-                    self.block_on(RW::Write)
-                },
-                Ok(Some(r)) => {
-                    return Ok(r);
-                },
-                Err(e) => {
-                    return Err(e)
-                }
-            }
-        }
+        let size = try!(self.try_until(RW::Write, |io| io.try_write(buf)));
+        self.count_written(size);
+        Ok(size)
     }
 
     /// Flush. This currently does nothing
@@ -560,12 +1160,15 @@ fn select_impl_set_mask_from_indices(indices : &[EventSourceIndex], blocked_on_m
     }
 }
 
-fn select_impl_set_mask_rc_handles(handles : &[Weak<RefCell<EventSourceShared>>], blocked_on_mask : &mut u32) {
+fn select_impl_set_mask_rc_handles(handles : &[Option<Weak<RefCell<EventSourceShared>>>], blocked_on_mask : &mut u32) {
     {
         *blocked_on_mask = 0;
-        for handle in handles {
+        for handle in handles.iter().filter_map(|handle| handle.as_ref()) {
             let io = handle.upgrade().unwrap();
-            *blocked_on_mask |= 1u32 << io.borrow().index;
+            let io = io.borrow();
+            if !io.internal {
+                *blocked_on_mask |= 1u32 << io.index;
+            }
         }
     }
 }
@@ -577,68 +1180,186 @@ impl MiocoHandle {
     /// `f` is routine handling connection. It must not use any real blocking-IO operations, only
     /// `mioco` provided types (`TypedEventSource`) and `MiocoHandle` functions. Otherwise `mioco`
     /// cooperative scheduling can block on real blocking-IO which defeats using mioco.
-    pub fn spawn<F>(&self, f : F)
+    ///
+    /// Returned `JoinHandle` can be used to cancel the coroutine.
+    pub fn spawn<F>(&self, f : F) -> JoinHandle
         where F : FnOnce(&mut MiocoHandle) -> io::Result<()> + 'static {
-            let coroutine_ref = spawn_impl(f, self.coroutine.borrow().server_shared.clone());
-            self.coroutine.borrow_mut().children_to_start.push(coroutine_ref);
+            self.spawn_child(f, None)
+        }
+
+    /// Create a `mioco` coroutine handler with a `name`
+    ///
+    /// The name is included in `mioco` log records. See `MiocoHandle::spawn()`.
+    pub fn spawn_named<S, F>(&self, name : S, f : F) -> JoinHandle
+        where S : Into<String>,
+              F : FnOnce(&mut MiocoHandle) -> io::Result<()> + 'static {
+            self.spawn_child(f, Some(name.into()))
+        }
+
+    fn spawn_child<F>(&self, f : F, name : Option<String>) -> JoinHandle
+        where F : FnOnce(&mut MiocoHandle) -> io::Result<()> + 'static {
+            let coroutine_ref = spawn_impl(f, self.coroutine.borrow().server_shared.clone(), name);
+            self.coroutine.borrow_mut().children_to_start.push(coroutine_ref.clone());
+
+            JoinHandle {
+                coroutine: coroutine_ref,
+            }
+        }
+
+    /// Id of the current coroutine
+    pub fn id(&self) -> CoroutineId {
+        CoroutineId(self.coroutine.borrow().id)
+    }
+
+    /// Name of the current coroutine, if it was given one
+    pub fn name(&self) -> Option<String> {
+        self.coroutine.borrow().name.clone()
+    }
+
+    /// Traffic of the current coroutine
+    ///
+    /// Includes all the sources it has used, including the released ones,
+    /// and `fs::File`-s. Sources used internally by `mioco` (eg. to wait for
+    /// the thread pool) are not included.
+    pub fn traffic(&self) -> Traffic {
+        self.coroutine.borrow().traffic()
+    }
+
+    /// Snapshot of `mioco` runtime counters
+    pub fn stats(&self) -> Stats {
+        let co = self.coroutine.borrow();
+        let shared = co.server_shared.borrow();
+        shared.stats()
+    }
+
+    /// Describe all the coroutines running in this `mioco` instance
+    ///
+    /// Useful for finding out what a hanging server is waiting for.
+    /// See also `Mioco::enable_sigquit_dump()`.
+    pub fn dump(&self) -> Vec<CoroutineInfo> {
+        let co = self.coroutine.borrow();
+        let shared = co.server_shared.borrow();
+        shared.dump()
+    }
+
+    /// Run `f` spawning child coroutines in a `Scope`
+    ///
+    /// Doesn't return until all the children spawned in the `Scope` finish,
+    /// so they can borrow data from the stack of the current coroutine.
+    /// The first error - returned by `f` or any of the children - cancels
+    /// all the children that are still running, and is returned.
+    ///
+    /// If waiting for the children fails repeatedly, the process is aborted,
+    /// as returning could leave them with dangling borrows.
+    pub fn scope<'a, F, R>(&self, f : F) -> io::Result<R>
+        where F : FnOnce(&mut Scope<'a>) -> io::Result<R> {
+            let (reader, writer) = try!(thread_pool::completion(&self.coroutine));
+            let mut scope = Scope {
+                mioco: MiocoHandle { coroutine: self.coroutine.clone() },
+                children: Vec::new(),
+                reader: reader,
+                waker: writer,
+                _marker: PhantomData,
+            };
+
+            let res = f(&mut scope);
+            let wait_res = scope.wait(res.is_err());
+
+            match res {
+                Ok(r) => wait_res.map(|_| r),
+                Err(e) => Err(e),
+            }
         }
 
+    /// Was the current coroutine cancelled
+    ///
+    /// See `JoinHandle::cancel()`.
+    pub fn is_cancelled(&self) -> bool {
+        self.coroutine.borrow().cancelled
+    }
+
     /// Register `mio`'s native io type to be used within `mioco` coroutine
     ///
     /// Consumes the `io`, returns a mioco wrapper over it. Use this wrapped IO
     /// to perform IO.
+    ///
+    /// A coroutine can hold up to 32 event sources, and panics when it runs out.
+    /// Wrapped IO stays registered until the coroutine finishes, while types
+    /// like `net::TcpStream` release their sources as soon as they're dropped.
     pub fn wrap<T : 'static>(&mut self, io : T) -> TypedEventSource<T>
     where T : Evented {
-        let token = {
-            let co = self.coroutine.borrow();
-            let mut shared = co.server_shared.borrow_mut();
-            shared.sources.insert_with(|token| {
-                EventSource {
-                    inn: Rc::new(RefCell::new(
-                                 EventSourceShared {
-                                     coroutine: self.coroutine.clone(),
-                                     io: Box::new(io),
-                                     token: token,
-                                     peer_hup: false,
-                                     index: self.coroutine.borrow().io.len(),
-                                     registered: false,
-                                 }
-                                 )),
-                }
-            })
-        }.expect("run out of tokens");
-        trace!("Added source token={:?}", token);
+        wrap_impl(&self.coroutine, io)
+    }
 
-        let io = {
-            let co = self.coroutine.borrow();
-            let shared = co.server_shared.borrow_mut();
-            shared.sources[token].inn.clone()
-        };
+    /// Resolve `host` to a list of socket addresses
+    ///
+    /// System resolver is used, but in a thread pool, so only the current
+    /// coroutine is blocked until the resolution is complete. See
+    /// `dns::Resolver` for querying a given name server directly.
+    pub fn resolve(&self, host : &str, port : u16) -> io::Result<Vec<SocketAddr>> {
+        let host = host.to_owned();
+        thread_pool::offload(&self.coroutine, move || {
+            (&host[..], port).to_socket_addrs().map(|addrs| addrs.collect())
+        })
+    }
 
-        let handle = TypedEventSource {
-            inn: io.clone(),
-            _t: PhantomData,
+    /// Start graceful shutdown of `mioco`
+    ///
+    /// Listening sockets of `net` and `unix` modules are shut down, so they
+    /// don't accept new connections anymore. Coroutines blocked on them, in
+    /// `accept` or `select`-like operations, are woken up with an error, and
+    /// blocking `accept` calls fail from now on. Event sources returned by
+    /// `shutdown_event()` become readable. Other coroutines can finish their
+    /// work, until the deadline set with `Mioco::set_shutdown_timeout()`.
+    pub fn shutdown(&self) {
+        let co = self.coroutine.borrow();
+        co.server_shared.borrow_mut().shutdown_requested = true;
+    }
+
+    /// Is `mioco` shutting down
+    pub fn is_shutting_down(&self) -> bool {
+        let co = self.coroutine.borrow();
+        let shared = co.server_shared.borrow();
+        shared.shutting_down || shared.shutdown_requested
+    }
+
+    /// Create an event source becoming readable when shutdown starts
+    ///
+    /// Use it in `select`-like operations to get notified about the shutdown.
+    /// Reading from it returns end of file.
+    pub fn shutdown_event(&mut self) -> io::Result<TypedEventSource<PipeReader>> {
+        let fd = {
+            let co = self.coroutine.borrow();
+            let mut shared = co.server_shared.borrow_mut();
+            try!(shared.shutdown_reader_fd())
         };
 
-        self.coroutine.borrow_mut().io.push(io.clone().downgrade());
+        let fd = try!(sys::cvt(unsafe { libc::dup(fd) }));
+        let reader = unsafe { PipeReader::from_raw_fd(fd) };
+        try!(sys::set_cloexec(fd));
 
-        handle
+        Ok(self.wrap(reader))
     }
 
     /// Wait till a read event is ready
-    fn select_impl(&mut self, rw : RW) -> LastEvent {
+    fn select_impl(&mut self, rw : RW) -> io::Result<LastEvent> {
+        try!(self.coroutine.borrow_mut().before_block());
         self.coroutine.borrow_mut().state = State::BlockedOn(rw);
         coroutine::Coroutine::block();
+        set_current(Some(self.coroutine.clone()));
+        try!(self.coroutine.borrow_mut().after_block());
         debug_assert!(self.coroutine.borrow().state == State::Running);
 
-        self.coroutine.borrow().last_event
+        Ok(self.coroutine.borrow().last_event)
     }
 
     /// Wait till an event is ready
     ///
     /// The returned value contains event type and the index id of the `TypedEventSource`.
-    /// See `TypedEventSource::index()`.
-    pub fn select(&mut self) -> LastEvent {
+    /// See `TypedEventSource::index()`. Fails if the coroutine was cancelled.
+    ///
+    /// Sources used internally by `mioco` (eg. to wait for the thread pool) are not included.
+    pub fn select(&mut self) -> io::Result<LastEvent> {
         {
             let Coroutine {
                 ref io,
@@ -654,7 +1375,7 @@ impl MiocoHandle {
     /// Wait till a read event is ready
     ///
     /// See `MiocoHandle::select`.
-    pub fn select_read(&mut self) -> LastEvent {
+    pub fn select_read(&mut self) -> io::Result<LastEvent> {
         {
             let Coroutine {
                 ref io,
@@ -670,7 +1391,7 @@ impl MiocoHandle {
     /// Wait till a read event is ready.
     ///
     /// See `MiocoHandle::select`.
-    pub fn select_write(&mut self) -> LastEvent {
+    pub fn select_write(&mut self) -> io::Result<LastEvent> {
         {
             let Coroutine {
                 ref io,
@@ -687,7 +1408,7 @@ impl MiocoHandle {
     ///
     /// See `TypedEventSource::index()`.
     /// See `MiocoHandle::select()`.
-    pub fn select_from(&mut self, indices : &[EventSourceIndex]) -> LastEvent {
+    pub fn select_from(&mut self, indices : &[EventSourceIndex]) -> io::Result<LastEvent> {
         {
             let Coroutine {
                 ref mut blocked_on_mask,
@@ -703,7 +1424,7 @@ impl MiocoHandle {
     /// Wait till write event is ready on a set of Handles.
     ///
     /// See `MiocoHandle::select_from`.
-    pub fn select_write_from(&mut self, indices : &[EventSourceIndex]) -> LastEvent {
+    pub fn select_write_from(&mut self, indices : &[EventSourceIndex]) -> io::Result<LastEvent> {
         {
             let Coroutine {
                 ref mut blocked_on_mask,
@@ -719,7 +1440,7 @@ impl MiocoHandle {
     /// Wait till read event is ready on a set of Handles.
     ///
     /// See `MiocoHandle::select_from`.
-    pub fn select_read_from(&mut self, indices : &[EventSourceIndex]) -> LastEvent {
+    pub fn select_read_from(&mut self, indices : &[EventSourceIndex]) -> io::Result<LastEvent> {
         {
             let Coroutine {
                 ref mut blocked_on_mask,
@@ -733,6 +1454,74 @@ impl MiocoHandle {
     }
 }
 
+thread_local!(static CURRENT_COROUTINE : RefCell<Option<RefCoroutine>> = RefCell::new(None));
+
+/// Set `Coroutine` currently running in this thread
+fn set_current(coroutine : Option<RefCoroutine>) {
+    CURRENT_COROUTINE.with(|current| *current.borrow_mut() = coroutine);
+}
+
+/// Get `MiocoHandle` of the `Coroutine` currently running in this thread
+///
+/// Panics when called outside of `mioco` coroutine.
+fn current_handle() -> MiocoHandle {
+    CURRENT_COROUTINE.with(|current| {
+        MiocoHandle {
+            coroutine: current.borrow().as_ref().expect("not running in mioco coroutine").clone(),
+        }
+    })
+}
+
+/// Register `io` in a `coroutine`
+///
+/// See `MiocoHandle::wrap()`.
+fn wrap_impl<T : 'static>(coroutine : &RefCoroutine, io : T) -> TypedEventSource<T>
+where T : Evented {
+    let index = coroutine.borrow().free_index();
+    let token = {
+        let co = coroutine.borrow();
+        let mut shared = co.server_shared.borrow_mut();
+        shared.stats.sources += 1;
+        shared.sources.insert_with(|token| {
+            EventSource {
+                inn: Rc::new(RefCell::new(
+                             EventSourceShared {
+                                 coroutine: coroutine.clone(),
+                                 io: Box::new(io),
+                                 token: token,
+                                 peer_hup: false,
+                                 index: index,
+                                 registered: false,
+                                 traffic: Traffic::default(),
+                                 accounted_traffic: Traffic::default(),
+                                 internal: false,
+                                 released: false,
+                                 lazy: false,
+                                 orphaned: false,
+                                 listen_fd: None,
+                             }
+                             )),
+            }
+        })
+    }.expect("run out of tokens");
+    trace!("Added source token={:?}", token);
+
+    let io = {
+        let co = coroutine.borrow();
+        let shared = co.server_shared.borrow_mut();
+        shared.sources[token].inn.clone()
+    };
+
+    let handle = TypedEventSource {
+        inn: io.clone(),
+        _t: PhantomData,
+    };
+
+    coroutine.borrow_mut().set_source(index, io.clone().downgrade());
+
+    handle
+}
+
 type RefServerShared = Rc<RefCell<ServerShared>>;
 /// Data belonging to `Server`, but referenced and manipulated by `Coroutine`-es
 /// belonging to it.
@@ -746,18 +1535,451 @@ struct ServerShared {
 
     /// Number of `Coroutine`-s running in the `Server`.
     coroutines_no : u32,
+
+    /// Pool for blocking operations. Started on first use.
+    thread_pool : Option<ThreadPool>,
+
+    /// All `Coroutine`-s running in the `Server`, by their id
+    coroutines : HashMap<usize, RefCoroutine>,
+
+    /// Id of the last spawned `Coroutine`
+    last_coroutine_id : usize,
+
+    /// Cancelled `Coroutine`-s, to be interrupted by `Server`
+    to_cancel : Vec<RefCoroutine>,
+
+    /// Released sources, to be deregistered by `Server`
+    to_release : Vec<RefEventSourceShared>,
+
+    /// `MiocoHandle::shutdown()` was called, but `Server` has not handled it yet
+    shutdown_requested : bool,
+
+    /// Shutdown has started
+    shutting_down : bool,
+
+    /// Time to wait for `Coroutine`-s to finish after shutdown has started
+    shutdown_timeout_ms : Option<u64>,
+
+    /// Pipe closed when shutdown starts, to notify `Coroutine`-s. Created on first use.
+    shutdown_pipe : Option<(PipeReader, Option<PipeWriter>)>,
+
+    /// Counters reported by `MiocoHandle::stats()`
+    stats : Stats,
+
+    /// When the current event loop iteration started processing events
+    iteration_start_ns : Option<u64>,
+
+    /// When `Stats::resumes_per_sec` was last updated, and `Stats::resumes` back then
+    rate_start : (u64, u64),
+
+    /// `Stats` published for `StatsHandle`-s. Created on first use.
+    published_stats : Option<Arc<Mutex<Stats>>>,
+
+    /// Source of `SIGCHLD` for `process::Child::wait()`. Created on first spawn.
+    sigchld : Option<SignalSource>,
+
+    /// `sigchld` was registered in `EventLoop` by `Server`
+    sigchld_registered : bool,
+
+    /// Completion pipes of `Coroutine`-s waiting for a child process to exit
+    child_waiters : Vec<Arc<PipeWriter>>,
 }
 
 impl ServerShared {
     fn new() -> Self {
         ServerShared {
-            sources: Slab::new(1024),
+            sources: Slab::new(MAX_SOURCES),
             coroutines_no: 0,
+            thread_pool: None,
+            coroutines: HashMap::new(),
+            last_coroutine_id: 0,
+            to_cancel: Vec::new(),
+            to_release: Vec::new(),
+            shutdown_requested: false,
+            shutting_down: false,
+            shutdown_timeout_ms: None,
+            shutdown_pipe: None,
+            stats: Stats::default(),
+            iteration_start_ns: None,
+            rate_start: (sys::precise_time_ns(), 0),
+            published_stats: None,
+            sigchld: None,
+            sigchld_registered: false,
+            child_waiters: Vec::new(),
+        }
+    }
+
+    /// Current `Stats`
+    fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        stats.coroutines = self.coroutines_no as usize;
+        stats.coroutines_total = self.last_coroutine_id;
+        stats.sources_capacity = MAX_SOURCES;
+        stats
+    }
+
+    /// Mark start of processing events in the current event loop iteration
+    fn iteration_started(&mut self) {
+        if self.iteration_start_ns.is_none() {
+            self.iteration_start_ns = Some(sys::precise_time_ns());
+        }
+    }
+
+    /// Update `Stats` at the end of an event loop iteration
+    fn iteration_finished(&mut self) {
+        let now = sys::precise_time_ns();
+        self.stats.iterations += 1;
+
+        if let Some(start) = self.iteration_start_ns.take() {
+            let us = (now - start) / 1000;
+            self.stats.last_iteration_us = us;
+            if us > self.stats.max_iteration_us {
+                self.stats.max_iteration_us = us;
+            }
+        }
+
+        let (rate_start_ns, rate_start_resumes) = self.rate_start;
+        let elapsed_ns = now - rate_start_ns;
+        if elapsed_ns >= 1000_000_000 {
+            self.stats.resumes_per_sec = (self.stats.resumes - rate_start_resumes) * 1000_000_000 / elapsed_ns;
+            self.rate_start = (now, self.stats.resumes);
+        }
+
+        self.publish_stats();
+    }
+
+    /// Update `Stats` read by `StatsHandle`-s, if there are any
+    fn publish_stats(&self) {
+        if let Some(ref published) = self.published_stats {
+            *published.lock().unwrap() = self.stats();
+        }
+    }
+
+    fn thread_pool(&mut self) -> ThreadPool {
+        if self.thread_pool.is_none() {
+            self.thread_pool = Some(ThreadPool::new(thread_pool::DEFAULT_SIZE));
+        }
+        self.thread_pool.as_ref().unwrap().clone()
+    }
+
+    /// Describe all the `Coroutine`-s, sorted by id
+    fn dump(&self) -> Vec<CoroutineInfo> {
+        let now_ns = sys::precise_time_ns();
+        let mut infos : Vec<CoroutineInfo> = self.coroutines.values().map(|co| co.borrow().info(now_ns)).collect();
+        infos.sort_by(|a, b| a.id.cmp(&b.id));
+        infos
+    }
+
+    /// Reading end of the shutdown notification pipe
+    fn shutdown_reader_fd(&mut self) -> io::Result<RawFd> {
+        if self.shutdown_pipe.is_none() {
+            let (reader, writer) = try!(mio::unix::pipe());
+            let writer = if self.shutting_down { None } else { Some(writer) };
+            self.shutdown_pipe = Some((reader, writer));
+        }
+
+        Ok(self.shutdown_pipe.as_ref().unwrap().0.as_raw_fd())
+    }
+}
+
+/// Maximum number of event sources registered at the same time
+const MAX_SOURCES : usize = 1024;
+
+/// Maximum number of event sources of a single `Coroutine`, limited by width of the masks
+const MAX_COROUTINE_SOURCES : usize = 32;
+
+/// Snapshot of `mioco` runtime counters
+///
+/// See `MiocoHandle::stats()` and `Mioco::stats_handle()`.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// Coroutines running now
+    pub coroutines : usize,
+    /// Coroutines spawned since start
+    pub coroutines_total : usize,
+    /// Event sources registered now
+    pub sources : usize,
+    /// Maximum number of event sources registered at the same time
+    pub sources_capacity : usize,
+    /// Coroutine resumes since start
+    pub resumes : u64,
+    /// Coroutine resumes per second, over the last second or so
+    pub resumes_per_sec : u64,
+    /// Events ignored, because their source was already gone
+    pub spurious_wakeups : u64,
+    /// Event loop iterations since start
+    pub iterations : u64,
+    /// Time spent handling events in the last event loop iteration, in microseconds
+    pub last_iteration_us : u64,
+    /// Longest time spent handling events in an event loop iteration, in microseconds
+    pub max_iteration_us : u64,
+}
+
+/// Handle reading `Stats` from any thread
+///
+/// `Stats` are published by the event loop at the end of every iteration, so
+/// they reflect the state after the last handled events. `resumes_per_sec` is
+/// still updated only about once a second.
+#[derive(Clone)]
+pub struct StatsHandle {
+    inn : Arc<Mutex<Stats>>,
+}
+
+impl StatsHandle {
+    /// Last published `Stats`
+    pub fn get(&self) -> Stats {
+        self.inn.lock().unwrap().clone()
+    }
+}
+
+/// Error returned by blocking operations interrupted by shutdown
+fn shutdown_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "mioco is shutting down")
+}
+
+/// Cause of errors returned by blocking operations of a cancelled coroutine
+///
+/// It's wrapped in `io::Error` of `ErrorKind::Other`, and not `Interrupted`, as
+/// `std` helpers like `read_to_end()` retry on the latter. Use `is_cancelled()`
+/// to recognize it.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl Error for Cancelled {
+    fn description(&self) -> &str {
+        "coroutine cancelled"
+    }
+}
+
+fn cancelled_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, Cancelled)
+}
+
+/// Was `err` caused by cancellation of the coroutine
+pub fn is_cancelled(err : &io::Error) -> bool {
+    err.get_ref().map_or(false, |e| e.is::<Cancelled>())
+}
+
+/// Unique identifier of a coroutine
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CoroutineId(usize);
+
+impl CoroutineId {
+    /// Numeric value of the id
+    pub fn as_usize(&self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for CoroutineId {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Handle to a spawned coroutine
+///
+/// Dropping it does not affect the coroutine.
+pub struct JoinHandle {
+    coroutine : RefCoroutine,
+}
+
+impl JoinHandle {
+    /// Id of the coroutine
+    pub fn id(&self) -> CoroutineId {
+        CoroutineId(self.coroutine.borrow().id)
+    }
+
+    /// Cancel the coroutine
+    ///
+    /// Its current and all the following blocking operations fail with `Cancelled`
+    /// error, so it can unwind and finish, releasing its event sources.
+    pub fn cancel(&self) {
+        cancel(&self.coroutine);
+    }
+
+    /// Create a token that can cancel the coroutine
+    pub fn cancel_token(&self) -> CancelToken {
+        CancelToken {
+            coroutine: self.coroutine.clone(),
+        }
+    }
+
+    /// Has the coroutine finished
+    pub fn is_finished(&self) -> bool {
+        self.coroutine.borrow().state == State::Finished
+    }
+}
+
+/// Child coroutines spawned within `MiocoHandle::scope()`
+///
+/// Children may borrow anything that outlives `'a`.
+pub struct Scope<'a> {
+    mioco : MiocoHandle,
+    /// Children that were not waited for yet
+    children : Vec<RefCoroutine>,
+    reader : TypedEventSource<PipeReader>,
+    waker : Arc<PipeWriter>,
+    /// Make `'a` invariant
+    _marker : PhantomData<Cell<&'a ()>>,
+}
+
+impl<'a> Scope<'a> {
+    /// Spawn a child coroutine in the `Scope`
+    ///
+    /// See `MiocoHandle::spawn()`.
+    pub fn spawn<F>(&mut self, f : F) -> JoinHandle
+        where F : FnOnce(&mut MiocoHandle) -> io::Result<()> + 'a {
+            let mut f = Some(f);
+            let f : Box<FnMut(&mut MiocoHandle) -> io::Result<()> + 'a> =
+                Box::new(move |mioco| (f.take().unwrap())(mioco));
+
+            // `Scope` does not let `'a` end before the child finishes, so it
+            // can be treated as `'static`
+            let mut f : Box<FnMut(&mut MiocoHandle) -> io::Result<()> + 'static> =
+                unsafe { mem::transmute(f) };
+
+            let handle = self.mioco.spawn(move |mioco| f(mioco));
+            handle.coroutine.borrow_mut().join_waker = Some(self.waker.clone());
+            self.children.push(handle.coroutine.clone());
+            handle
+        }
+
+    /// Handle of the coroutine running the `Scope`
+    pub fn mioco(&mut self) -> &mut MiocoHandle {
+        &mut self.mioco
+    }
+
+    /// Block until all the children finish
+    ///
+    /// If `failed`, or on the first error returned by a child, all the
+    /// children are cancelled.
+    fn wait(&mut self, failed : bool) -> io::Result<()> {
+        // Children must finish even if the parent is cancelled, so stop
+        // blocking operations from failing while waiting for them
+        let mut was_cancelled = mem::replace(&mut self.mioco.coroutine.borrow_mut().cancelled, false);
+        if failed || was_cancelled {
+            self.cancel_all();
+        }
+
+        let mut first_err = None;
+        // Waiting for the children failed once already
+        let mut wait_failed = false;
+        loop {
+            let mut new_err = None;
+            self.children.retain(|child| {
+                let mut child = child.borrow_mut();
+                if child.state != State::Finished {
+                    return true;
+                }
+
+                let res = child.result.take().unwrap_or_else(|| {
+                    Err(io::Error::new(io::ErrorKind::Other, "coroutine panicked"))
+                });
+                if let Err(e) = res {
+                    if new_err.is_none() {
+                        new_err = Some(e);
+                    }
+                }
+                false
+            });
+
+            if first_err.is_none() && new_err.is_some() {
+                first_err = new_err;
+                self.cancel_all();
+            }
+
+            if self.children.is_empty() {
+                break;
+            }
+
+            let mut buf = [0u8; 64];
+            match self.reader.read(&mut buf) {
+                Err(ref e) if is_cancelled(e) => {
+                    was_cancelled = true;
+                    self.mioco.coroutine.borrow_mut().cancelled = false;
+                    self.cancel_all();
+                },
+                Err(e) => {
+                    if wait_failed {
+                        // children might still borrow from the stack of the
+                        // parent, so it must not return before they finish
+                        error!("Scope: waiting for children failed again: {}, aborting", e);
+                        unsafe { libc::abort() };
+                    }
+                    error!("Scope: waiting for children failed: {}, cancelling them", e);
+                    wait_failed = true;
+                    if first_err.is_none() {
+                        first_err = Some(e);
+                    }
+                    self.cancel_all();
+                },
+                Ok(_) => {},
+            }
+        }
+
+        if was_cancelled {
+            self.mioco.coroutine.borrow_mut().cancelled = true;
+            return Err(cancelled_error());
+        }
+
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn cancel_all(&self) {
+        for child in &self.children {
+            cancel(child);
         }
     }
 }
 
-fn spawn_impl<F>(f : F, server : RefServerShared) -> RefCoroutine
+impl<'a> Drop for Scope<'a> {
+    /// Wait for the children if `MiocoHandle::scope()` is unwinding,
+    /// as they might be borrowing from its stack
+    fn drop(&mut self) {
+        if !self.children.is_empty() {
+            let _ = self.wait(true);
+        }
+    }
+}
+
+/// Token cancelling a coroutine
+///
+/// See `JoinHandle::cancel()`.
+#[derive(Clone)]
+pub struct CancelToken {
+    coroutine : RefCoroutine,
+}
+
+impl CancelToken {
+    /// Cancel the coroutine
+    pub fn cancel(&self) {
+        cancel(&self.coroutine);
+    }
+}
+
+/// Mark `coroutine` cancelled, and let `Server` interrupt it if it's blocked
+fn cancel(coroutine : &RefCoroutine) {
+    let mut co = coroutine.borrow_mut();
+    if co.cancelled || co.state == State::Finished {
+        return;
+    }
+
+    trace!("Coroutine {}: cancelling", co.label());
+    co.cancelled = true;
+    co.server_shared.borrow_mut().to_cancel.push(coroutine.clone());
+}
+
+fn spawn_impl<F>(f : F, server : RefServerShared, name : Option<String>) -> RefCoroutine
 where F : FnOnce(&mut MiocoHandle) -> io::Result<()> + 'static {
 
 
@@ -782,10 +2004,18 @@ where F : FnOnce(&mut MiocoHandle) -> io::Result<()> + 'static {
     // Same logic as in `SendFnOnce` applies here.
     unsafe impl Send for SendRefCoroutine { }
 
-    trace!("Coroutine: spawning");
-    server.borrow_mut().coroutines_no += 1;
+    let id = {
+        let mut shared = server.borrow_mut();
+        shared.coroutines_no += 1;
+        shared.last_coroutine_id += 1;
+        shared.last_coroutine_id
+    };
 
-    let coroutine_ref = Rc::new(RefCell::new(Coroutine::new(server)));
+    let coroutine_ref = Rc::new(RefCell::new(Coroutine::new(server.clone(), id, name)));
+    server.borrow_mut().coroutines.insert(id, coroutine_ref.clone());
+
+    let label = coroutine_ref.borrow().label();
+    trace!("Coroutine {}: spawning", label);
 
     let sendref = SendRefCoroutine {
         coroutine: coroutine_ref.clone(),
@@ -796,18 +2026,24 @@ where F : FnOnce(&mut MiocoHandle) -> io::Result<()> + 'static {
     };
 
     let coroutine_handle = coroutine::coroutine::Coroutine::spawn(move || {
-        trace!("Coroutine: started");
+        trace!("Coroutine {}: started", label);
+        set_current(Some(sendref.coroutine.clone()));
         let mut mioco_handle = MiocoHandle {
             coroutine: sendref.coroutine,
         };
 
         let SendFnOnce { f } = send_f;
 
-        let _res = f(&mut mioco_handle);
+        let res = f(&mut mioco_handle);
+        if let Err(ref e) = res {
+            debug!("Coroutine {}: failed: {}", label, e);
+        }
 
+        mioco_handle.coroutine.borrow_mut().result = Some(res);
         mioco_handle.coroutine.borrow_mut().state = State::Finished;
         mioco_handle.coroutine.borrow_mut().blocked_on_mask = 0;
-        trace!("Coroutine: finished");
+        set_current(None);
+        trace!("Coroutine {}: finished", label);
     });
 
     coroutine_ref.borrow_mut().handle = Some(coroutine_handle);
@@ -818,19 +2054,181 @@ where F : FnOnce(&mut MiocoHandle) -> io::Result<()> + 'static {
 /// `Server` registered in `mio::EventLoop` and implementing `mio::Handler`.
 pub struct Server {
     shared : RefServerShared,
+
+    /// Source of `SIGQUIT` triggering coroutine dump, if enabled
+    dump_signal : Option<SignalSource>,
 }
 
 impl Server {
     fn new(shared : RefServerShared) -> Self {
         Server {
             shared: shared,
+            dump_signal: None,
         }
     }
+
+    /// Print dump of all the `Coroutine`-s to stderr
+    #[cfg(target_os = "linux")]
+    fn print_dump(&mut self) {
+        if let Some(ref signals) = self.dump_signal {
+            while let Ok(Some(_)) = signals.try_read_signal() {}
+        }
+
+        let infos = self.shared.borrow().dump();
+        let mut stderr = io::stderr();
+        let _ = writeln!(stderr, "mioco: {} coroutines", infos.len());
+        for info in &infos {
+            let _ = writeln!(stderr, "{}", info);
+        }
+    }
+
+    /// Handle event of one of `Server` signal sources
+    ///
+    /// Returns `false` if `token` does not belong to any.
+    #[cfg(target_os = "linux")]
+    fn signal_ready(&mut self, token : Token) -> bool {
+        if token == DUMP_SIGNAL_TOKEN {
+            self.print_dump();
+            return true;
+        }
+        if token == SIGCHLD_TOKEN {
+            self.wake_child_waiters();
+            return true;
+        }
+        false
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn signal_ready(&mut self, _token : Token) -> bool {
+        false
+    }
+
+    /// Wake up all the `Coroutine`-s waiting for a child process, on `SIGCHLD`
+    ///
+    /// `SIGCHLD`-s are merged, so every waiter checks its child itself.
+    #[cfg(target_os = "linux")]
+    fn wake_child_waiters(&mut self) {
+        let waiters = {
+            let mut shared = self.shared.borrow_mut();
+            if let Some(ref sigchld) = shared.sigchld {
+                while let Ok(Some(_)) = sigchld.try_read_signal() {}
+            }
+            mem::replace(&mut shared.child_waiters, Vec::new())
+        };
+
+        trace!("SIGCHLD: waking up {} waiters", waiters.len());
+        for writer in &waiters {
+            let _ = nix::unistd::write(writer.as_raw_fd(), &[0u8]);
+        }
+    }
+
+    /// Handle requests of `Coroutine`-s that have just run
+    fn handle_requests(&mut self, event_loop: &mut EventLoop<Server>) {
+        loop {
+            let to_cancel = mem::replace(&mut self.shared.borrow_mut().to_cancel, Vec::new());
+            if to_cancel.is_empty() {
+                break;
+            }
+
+            // Interrupted `Coroutine`-s might cancel others
+            for coroutine in &to_cancel {
+                Coroutine::interrupt(coroutine, event_loop, cancelled_error());
+            }
+        }
+
+        let requested = mem::replace(&mut self.shared.borrow_mut().shutdown_requested, false);
+        if requested {
+            self.shutdown(event_loop);
+            self.handle_requests(event_loop);
+        }
+
+        let mut shared = self.shared.borrow_mut();
+        let to_release = mem::replace(&mut shared.to_release, Vec::new());
+        for io in &to_release {
+            let mut io = io.borrow_mut();
+            io.deregister(event_loop);
+            trace!("Removing released source token={:?}", io.token);
+            shared.sources.remove(io.token).expect("cleared empty slot");
+            shared.stats.sources -= 1;
+        }
+
+        register_sigchld(&mut shared, event_loop);
+    }
+
+    /// Start graceful shutdown
+    fn shutdown(&mut self, event_loop: &mut EventLoop<Server>) {
+        let listening : Vec<RefCoroutine> = {
+            let mut shared = self.shared.borrow_mut();
+            if shared.shutting_down {
+                return;
+            }
+
+            debug!("Shutdown started");
+            shared.shutting_down = true;
+            if let Some((_, ref mut writer)) = shared.shutdown_pipe {
+                *writer = None;
+            }
+
+            if let Some(ms) = shared.shutdown_timeout_ms {
+                event_loop.timeout_ms(SHUTDOWN_TIMEOUT, ms).expect("timeout_ms failed");
+            }
+
+            for coroutine in shared.coroutines.values() {
+                coroutine.borrow().shutdown_listeners();
+            }
+
+            shared.coroutines.values().filter(|co| {
+                let co = co.borrow();
+                co.accepting || co.blocked_on_listener()
+            }).cloned().collect()
+        };
+
+        for coroutine in &listening {
+            Coroutine::interrupt(coroutine, event_loop, shutdown_error());
+        }
+    }
+}
+
+/// Stop accepting connections on listening socket `fd`, waking up its waiters
+fn shutdown_listener(fd : RawFd) {
+    unsafe { libc::shutdown(fd, libc::SHUT_RDWR) };
+}
+
+/// `Server::timeout()` token of the shutdown deadline
+const SHUTDOWN_TIMEOUT : usize = 0;
+
+/// Register `ServerShared::sigchld` created since the last time
+#[cfg(target_os = "linux")]
+fn register_sigchld(shared : &mut ServerShared, event_loop : &mut EventLoop<Server>) {
+    if !shared.sigchld_registered && shared.sigchld.is_some() {
+        event_loop.register_opt(shared.sigchld.as_ref().unwrap(), SIGCHLD_TOKEN,
+                                EventSet::readable(), mio::PollOpt::edge()).expect("register SIGCHLD");
+        shared.sigchld_registered = true;
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn register_sigchld(_shared : &mut ServerShared, _event_loop : &mut EventLoop<Server>) {
+}
+
+/// Token of `Server::dump_signal`, outside of `ServerShared::sources` range
+/// and not colliding with tokens reserved by `mio`
+#[cfg(target_os = "linux")]
+const DUMP_SIGNAL_TOKEN : Token = Token(std::usize::MAX - 1);
+
+/// Token of `ServerShared::sigchld`, like `DUMP_SIGNAL_TOKEN`
+#[cfg(target_os = "linux")]
+const SIGCHLD_TOKEN : Token = Token(std::usize::MAX - 2);
+
+/// Message sent to `Server` from outside the event loop
+pub enum Message {
+    /// Start graceful shutdown
+    Shutdown,
 }
 
 impl mio::Handler for Server {
     type Timeout = usize;
-    type Message = ();
+    type Message = Message;
 
     fn ready(&mut self, event_loop: &mut mio::EventLoop<Server>, token: mio::Token, events: mio::EventSet) {
         // It's possible we got an event for a Source that was deregistered
@@ -838,16 +2236,74 @@ impl mio::Handler for Server {
         // different source, we will wake it up needlessly. If it's empty, we just
         // ignore the event.
         trace!("Server::ready(token={:?})", token);
-        let mut source = match self.shared.borrow().sources.get(token) {
-            Some(source) => source.clone(),
+        if self.signal_ready(token) {
+            return;
+        }
+
+        self.shared.borrow_mut().iteration_started();
+        let source = self.shared.borrow().sources.get(token).map(|source| source.clone());
+        let mut source = match source {
+            Some(source) => source,
             None => {
                 trace!("Server::ready() ignored");
+                self.shared.borrow_mut().stats.spurious_wakeups += 1;
                 return
             },
         };
         source.ready(event_loop, token, events);
+        self.handle_requests(event_loop);
         trace!("Server::ready finished");
     }
+
+    fn notify(&mut self, event_loop: &mut mio::EventLoop<Server>, msg: Message) {
+        self.shared.borrow_mut().iteration_started();
+        match msg {
+            Message::Shutdown => self.shutdown(event_loop),
+        }
+        self.handle_requests(event_loop);
+    }
+
+    fn timeout(&mut self, event_loop: &mut mio::EventLoop<Server>, timeout: usize) {
+        debug_assert!(timeout == SHUTDOWN_TIMEOUT);
+        self.shared.borrow_mut().iteration_started();
+
+        let coroutines = mem::replace(&mut self.shared.borrow_mut().coroutines, HashMap::new());
+        warn!("Shutdown deadline passed - tearing down {} coroutines", coroutines.len());
+        for (_, coroutine) in coroutines {
+            let mut co = coroutine.borrow_mut();
+            co.deregister_all(event_loop);
+            co.state = State::Finished;
+        }
+        // stdio wrappers of torn down coroutines are never dropped
+        stdio::restore_flags();
+        self.handle_requests(event_loop);
+
+        let mut shared = self.shared.borrow_mut();
+        shared.coroutines_no = 0;
+        shared.stats.sources = 0;
+        event_loop.shutdown();
+    }
+
+    fn tick(&mut self, _event_loop: &mut mio::EventLoop<Server>) {
+        self.shared.borrow_mut().iteration_finished();
+    }
+}
+
+/// Handle starting graceful shutdown of `Mioco` from any thread
+///
+/// See `MiocoHandle::shutdown()`.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    tx : mio::Sender<Message>,
+}
+
+impl ShutdownHandle {
+    /// Start graceful shutdown
+    pub fn shutdown(&self) -> io::Result<()> {
+        self.tx.send(Message::Shutdown).map_err(|_| {
+            io::Error::new(io::ErrorKind::Other, "can't notify mioco event loop")
+        })
+    }
 }
 
 /// Mioco struct
@@ -866,6 +2322,45 @@ impl Mioco {
         }
     }
 
+    /// Create a handle to start graceful shutdown from other threads
+    pub fn shutdown_handle(&mut self) -> ShutdownHandle {
+        ShutdownHandle {
+            tx: self.event_loop.channel(),
+        }
+    }
+
+    /// Set time to wait for coroutines to finish after shutdown has started
+    ///
+    /// Sources of coroutines still running after the deadline are deregistered
+    /// and `start()` returns. By default there's no deadline.
+    pub fn set_shutdown_timeout(&mut self, timeout_ms : u64) {
+        self.server.shared.borrow_mut().shutdown_timeout_ms = Some(timeout_ms);
+    }
+
+    /// Create a handle to read `Stats` from other threads
+    pub fn stats_handle(&mut self) -> StatsHandle {
+        let mut shared = self.server.shared.borrow_mut();
+        if shared.published_stats.is_none() {
+            shared.published_stats = Some(Arc::new(Mutex::new(shared.stats())));
+        }
+
+        StatsHandle {
+            inn: shared.published_stats.as_ref().unwrap().clone(),
+        }
+    }
+
+    /// Print dump of all the coroutines to stderr on `SIGQUIT`
+    ///
+    /// `SIGQUIT` is blocked in the calling thread, so it does not terminate
+    /// the process anymore. See `MiocoHandle::dump()`.
+    #[cfg(target_os = "linux")]
+    pub fn enable_sigquit_dump(&mut self) -> io::Result<()> {
+        let signals = try!(SignalFd::new(&[signal::SIGQUIT]));
+        try!(self.event_loop.register_opt(&signals, DUMP_SIGNAL_TOKEN, EventSet::readable(), mio::PollOpt::edge()));
+        self.server.dump_signal = Some(signals);
+        Ok(())
+    }
+
     /// Start mioco handling
     ///
     /// Takes a starting handler function that will be executed in `mioco` environment.
@@ -883,21 +2378,18 @@ impl Mioco {
 
             let shared = server.shared.clone();
 
-            let coroutine_ref = spawn_impl(f, shared);
-
-            let coroutine_handle = coroutine_ref.borrow().handle.as_ref().map(|c| c.clone()).unwrap();
+            let coroutine_ref = spawn_impl(f, shared.clone(), None);
 
             trace!("Initial resume");
-            if let Err(reason) = coroutine_handle.resume() {
-                error!("Co resume failed: {:?} in start()", reason);
-                let mut co = coroutine_ref.borrow_mut();
-                co.state = State::Finished;
-                co.blocked_on_mask = 0;
-            }
-            coroutine_ref.borrow_mut().after_resume(event_loop);
+            Coroutine::resume(&coroutine_ref, "start()");
+            Coroutine::after_resume(&coroutine_ref, event_loop);
+
+            server.handle_requests(event_loop);
+            shared.borrow().publish_stats();
 
             trace!("Start event loop");
             event_loop.run(server).unwrap();
+            shared.borrow().publish_stats();
         }
 }
 
@@ -0,0 +1,476 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Networking primitives for `mioco` coroutines
+//!
+//! Types in this module mirror `std::net`, so porting blocking code is mostly
+//! a matter of changing imports. There's no need to `wrap` them manually: they
+//! register themselves in the coroutine that uses them first. This way a
+//! connection can be accepted in one coroutine and handled in a newly spawned one.
+//!
+//! Sockets can be created anywhere, but connecting, blocking operations and
+//! `index()` need the current coroutine, so they panic when called outside of
+//! `mioco` coroutine.
+
+#[cfg(target_os = "linux")]
+use std::env;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, IpAddr};
+use std::os::unix::io::{RawFd, AsRawFd, FromRawFd};
+
+use libc;
+use mio;
+use mio::{tcp, udp};
+use mio::buf::{Buf, MutBuf, SliceBuf, MutSliceBuf};
+use nix::sys::socket::{self, SockAddr, InetAddr};
+
+use super::{MiocoHandle, TypedEventSource, LazyEventSource, EventSourceIndex, Traffic, RW, current_handle};
+use super::sys;
+#[cfg(target_os = "linux")]
+use super::timerfd::TimerFd;
+#[cfg(target_os = "linux")]
+use super::unix::UnixListener;
+
+/// First file descriptor passed in socket activation protocol
+#[cfg(target_os = "linux")]
+const LISTEN_FDS_START : RawFd = 3;
+
+/// TCP socket listening for connections
+///
+/// Blocking operations and `index()` panic when called outside of `mioco` coroutine.
+pub struct TcpListener {
+    inn : LazyEventSource<tcp::TcpListener>,
+}
+
+impl TcpListener {
+    /// Create a listener bound to `addr`
+    pub fn bind(addr : &SocketAddr) -> io::Result<TcpListener> {
+        let sock = try!(match *addr {
+            SocketAddr::V4(..) => tcp::TcpSocket::v4(),
+            SocketAddr::V6(..) => tcp::TcpSocket::v6(),
+        });
+        try!(sock.set_reuseaddr(true));
+        try!(sock.bind(addr));
+        let listener = try!(sock.listen(1024));
+
+        Ok(TcpListener {
+            inn: LazyEventSource::listener(listener),
+        })
+    }
+
+    /// Block on accepting a new connection
+    ///
+    /// Returned `TcpStream` is not registered in any coroutine yet, so it can
+    /// be moved to a newly spawned one. Fails once `mioco` is shutting down.
+    pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let stream = try!(self.inn.with_source(|listener| listener.accept()));
+        let addr = try!(stream.peer_addr());
+
+        Ok((TcpStream {
+            inn: LazyEventSource::new(stream),
+        }, addr))
+    }
+
+    /// Create a listener from a listening socket `fd`, eg. received with
+    /// `unix::UnixStream::recv_fds()`
+    ///
+    /// Sets the `fd` non-blocking, and takes ownership of it on success.
+    pub unsafe fn from_fd(fd : RawFd) -> io::Result<TcpListener> {
+        try!(sys::set_nonblock(fd));
+        try!(sys::set_cloexec(fd));
+
+        Ok(TcpListener::from_raw_fd(fd))
+    }
+
+    /// Local address of the listener
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inn.with_raw(|listener| listener.local_addr())
+    }
+
+    /// Index identificator, for `select`-like operations
+    ///
+    /// Registers the listener in the current coroutine. See `TypedEventSource::index()`.
+    pub fn index(&self) -> EventSourceIndex {
+        self.inn.index()
+    }
+}
+
+impl FromRawFd for TcpListener {
+    /// Take ownership of a listening socket `fd`, that is already non-blocking
+    ///
+    /// See `TcpListener::from_fd()`.
+    unsafe fn from_raw_fd(fd : RawFd) -> TcpListener {
+        TcpListener {
+            inn: LazyEventSource::listener(tcp::TcpListener::from_raw_fd(fd)),
+        }
+    }
+}
+
+/// Listener inherited from a supervisor
+///
+/// See `listeners_from_env()`.
+#[cfg(target_os = "linux")]
+pub enum Listener {
+    /// TCP listener
+    Tcp(TcpListener),
+    /// Unix domain socket listener
+    Unix(UnixListener),
+    /// Any other file descriptor, eg. a datagram socket or a FIFO
+    ///
+    /// It's left as it was passed, except for close-on-exec flag. Closing it
+    /// is up to the caller.
+    Other(RawFd),
+}
+
+/// Kind of an inherited file descriptor, before taking ownership of it
+#[cfg(target_os = "linux")]
+enum EnvFd {
+    Tcp,
+    Unix,
+    Other,
+}
+
+/// Take listeners passed by a supervisor (systemd-style socket activation)
+///
+/// Validates `LISTEN_PID` and `LISTEN_FDS` environment variables and
+/// the passed file descriptors (starting with 3) first. Only then the
+/// variables are removed from the environment, so they are not inherited
+/// by child processes, and listening stream sockets are taken ownership of
+/// and set non-blocking. On error, environment and descriptors are left
+/// untouched, apart from close-on-exec and non-blocking flags.
+///
+/// Returns empty list if no descriptors were passed to this process.
+#[cfg(target_os = "linux")]
+pub fn listeners_from_env() -> io::Result<Vec<Listener>> {
+    let pid = match env::var("LISTEN_PID") {
+        Ok(pid) => pid,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let pid : libc::pid_t = try!(pid.parse().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "LISTEN_PID is not a number")
+    }));
+
+    if pid != unsafe { libc::getpid() } {
+        return Ok(Vec::new());
+    }
+
+    let fds : RawFd = try!(env::var("LISTEN_FDS").ok().and_then(|fds| fds.parse().ok()).ok_or(
+        io::Error::new(io::ErrorKind::InvalidInput, "LISTEN_FDS missing or not a number")
+    ));
+
+    let kinds : Vec<(RawFd, EnvFd)> = try!((LISTEN_FDS_START..LISTEN_FDS_START + fds).map(|fd| {
+        env_fd_kind(fd).map(|kind| (fd, kind))
+    }).collect());
+
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    Ok(kinds.into_iter().map(|(fd, kind)| match kind {
+        EnvFd::Tcp => Listener::Tcp(unsafe { TcpListener::from_raw_fd(fd) }),
+        EnvFd::Unix => Listener::Unix(unsafe { UnixListener::from_raw_fd(fd) }),
+        EnvFd::Other => Listener::Other(fd),
+    }).collect())
+}
+
+/// Check what inherited `fd` is, without taking ownership of it
+///
+/// Listening stream sockets are set non-blocking.
+#[cfg(target_os = "linux")]
+fn env_fd_kind(fd : RawFd) -> io::Result<EnvFd> {
+    try!(sys::set_cloexec(fd));
+
+    let ty : libc::c_int = match sys::getsockopt(fd, libc::SOL_SOCKET, libc::SO_TYPE) {
+        Ok(ty) => ty,
+        Err(ref e) if e.raw_os_error() == Some(libc::ENOTSOCK) => return Ok(EnvFd::Other),
+        Err(e) => return Err(e),
+    };
+    let listening : libc::c_int = try!(sys::getsockopt(fd, libc::SOL_SOCKET, libc::SO_ACCEPTCONN));
+
+    if listening == 0 || ty != libc::SOCK_STREAM {
+        return Ok(EnvFd::Other);
+    }
+
+    let domain : libc::c_int = try!(sys::getsockopt(fd, libc::SOL_SOCKET, libc::SO_DOMAIN));
+    let kind = match domain {
+        libc::AF_INET | libc::AF_INET6 => EnvFd::Tcp,
+        libc::AF_UNIX => EnvFd::Unix,
+        _ => return Ok(EnvFd::Other),
+    };
+
+    try!(sys::set_nonblock(fd));
+    Ok(kind)
+}
+
+/// TCP connection
+///
+/// Blocking operations and `index()` panic when called outside of `mioco` coroutine.
+pub struct TcpStream {
+    inn : LazyEventSource<tcp::TcpStream>,
+}
+
+impl TcpStream {
+    /// Open TCP connection to `addr`
+    ///
+    /// Blocks the current coroutine until the connection is established or fails.
+    /// Panics when called outside of `mioco` coroutine.
+    pub fn connect(addr : &SocketAddr) -> io::Result<TcpStream> {
+        TcpStream::connect_impl(addr, None)
+    }
+
+    /// Open TCP connection to `addr`, failing with `TimedOut` error if it was not
+    /// established within `timeout_ms` milliseconds
+    ///
+    /// Panics when called outside of `mioco` coroutine.
+    #[cfg(target_os = "linux")]
+    pub fn connect_timeout(addr : &SocketAddr, timeout_ms : u64) -> io::Result<TcpStream> {
+        TcpStream::connect_impl(addr, Some(timeout_ms))
+    }
+
+    fn connect_impl(addr : &SocketAddr, timeout_ms : Option<u64>) -> io::Result<TcpStream> {
+        let mut mioco = current_handle();
+        let stream = mioco.wrap(try!(tcp::TcpStream::connect(addr)));
+
+        if let Err(e) = TcpStream::wait_connected(&mut mioco, &stream, timeout_ms) {
+            stream.release();
+            return Err(e);
+        }
+
+        Ok(TcpStream {
+            inn: LazyEventSource::from_wrapped(stream),
+        })
+    }
+
+    /// Block until connection of `stream` is established or fails
+    fn wait_connected(mioco : &mut MiocoHandle, stream : &TypedEventSource<tcp::TcpStream>, timeout_ms : Option<u64>) -> io::Result<()> {
+        match timeout_ms {
+            None => try!(stream.block_on(RW::Both)),
+            Some(ms) => try!(TcpStream::wait_connected_timeout(mioco, stream, ms)),
+        }
+
+        stream.with_raw(|stream| stream.take_socket_error())
+    }
+
+    /// Like `wait_connected()`, failing with `TimedOut` error after `timeout_ms` milliseconds
+    #[cfg(target_os = "linux")]
+    fn wait_connected_timeout(mioco : &mut MiocoHandle, stream : &TypedEventSource<tcp::TcpStream>, timeout_ms : u64) -> io::Result<()> {
+        let timer = try!(TimerFd::new());
+        try!(timer.set_oneshot(timeout_ms));
+        let timer = mioco.wrap(timer);
+
+        let res = mioco.select_from(&[stream.index(), timer.index()]);
+        let timed_out = res.as_ref().map(|event| event.index() == timer.index()).unwrap_or(false);
+        timer.release();
+
+        try!(res);
+        if timed_out {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out"));
+        }
+        Ok(())
+    }
+
+    /// `connect_timeout()` needs `TimerFd`, so it's never called here
+    #[cfg(not(target_os = "linux"))]
+    fn wait_connected_timeout(_mioco : &mut MiocoHandle, _stream : &TypedEventSource<tcp::TcpStream>, _timeout_ms : u64) -> io::Result<()> {
+        unreachable!()
+    }
+
+    /// Remote address of the connection
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inn.with_raw(|stream| stream.peer_addr())
+    }
+
+    /// Local address of the connection
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inn.with_raw(|stream| stream.local_addr())
+    }
+
+    /// Set `TCP_NODELAY` option
+    pub fn set_nodelay(&self, nodelay : bool) -> io::Result<()> {
+        self.inn.with_raw(|stream| stream.set_nodelay(nodelay))
+    }
+
+    /// Index identificator, for `select`-like operations
+    ///
+    /// Registers the stream in the current coroutine. See `TypedEventSource::index()`.
+    pub fn index(&self) -> EventSourceIndex {
+        self.inn.index()
+    }
+
+    /// Traffic of the connection
+    ///
+    /// See `TypedEventSource::traffic()`.
+    pub fn traffic(&self) -> Traffic {
+        self.inn.traffic()
+    }
+}
+
+impl Read for TcpStream {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        self.inn.with_source(|stream| stream.read(buf))
+    }
+}
+
+impl Write for TcpStream {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        self.inn.with_source(|stream| stream.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inn.with_source(|stream| stream.flush())
+    }
+}
+
+/// UDP socket
+///
+/// Blocking operations and `index()` panic when called outside of `mioco` coroutine.
+pub struct UdpSocket {
+    inn : LazyEventSource<udp::UdpSocket>,
+}
+
+impl UdpSocket {
+    /// Create a socket bound to `addr`
+    pub fn bind(addr : &SocketAddr) -> io::Result<UdpSocket> {
+        let sock = try!(udp::UdpSocket::bound(addr));
+
+        Ok(UdpSocket {
+            inn: LazyEventSource::new(sock),
+        })
+    }
+
+    /// Local address of the socket
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inn.with_raw(|sock| sock.local_addr())
+    }
+
+    /// Block on receiving a datagram
+    ///
+    /// See `TypedEventSource<UdpSocket>::recv_from()`.
+    pub fn recv_from(&self, buf : &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.inn.with_source(|sock| sock.recv_from(buf))
+    }
+
+    /// Block on sending a datagram to `addr`
+    ///
+    /// See `TypedEventSource<UdpSocket>::send_to()`.
+    pub fn send_to(&self, buf : &[u8], addr : &SocketAddr) -> io::Result<usize> {
+        self.inn.with_source(|sock| sock.send_to(buf, addr))
+    }
+
+    /// Set the default destination of `send()` and the only source of `recv()`
+    pub fn connect(&self, addr : &SocketAddr) -> io::Result<()> {
+        self.inn.with_raw(|sock| connect_udp(sock, addr))
+    }
+
+    /// Block on receiving a datagram from the connected address
+    pub fn recv(&self, buf : &mut [u8]) -> io::Result<usize> {
+        self.inn.with_source(|sock| sock.recv(buf))
+    }
+
+    /// Block on sending a datagram to the connected address
+    pub fn send(&self, buf : &[u8]) -> io::Result<usize> {
+        self.inn.with_source(|sock| sock.send(buf))
+    }
+
+    /// Join multicast group `addr`
+    pub fn join_multicast(&self, addr : &IpAddr) -> io::Result<()> {
+        self.inn.with_raw(|sock| sock.join_multicast(&mio_ip_addr(addr)))
+    }
+
+    /// Leave multicast group `addr`
+    pub fn leave_multicast(&self, addr : &IpAddr) -> io::Result<()> {
+        self.inn.with_raw(|sock| sock.leave_multicast(&mio_ip_addr(addr)))
+    }
+
+    /// Index identificator, for `select`-like operations
+    ///
+    /// Registers the socket in the current coroutine. See `TypedEventSource::index()`.
+    pub fn index(&self) -> EventSourceIndex {
+        self.inn.index()
+    }
+
+    /// Traffic of the socket
+    ///
+    /// See `TypedEventSource::traffic()`.
+    pub fn traffic(&self) -> Traffic {
+        self.inn.traffic()
+    }
+}
+
+fn connect_udp(sock : &udp::UdpSocket, addr : &SocketAddr) -> io::Result<()> {
+    socket::connect(sock.as_raw_fd(), &SockAddr::Inet(InetAddr::from_std(addr))).map_err(sys::from_nix)
+}
+
+impl TypedEventSource<udp::UdpSocket> {
+    /// Block on receiving a datagram
+    ///
+    /// Returns number of bytes received and the address of the sender.
+    pub fn recv_from(&mut self, buf : &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let len = buf.len();
+        let (size, addr) = try!(self.try_until(RW::Read, |sock| {
+            let mut mut_buf = MutSliceBuf::wrap(&mut buf[..]);
+            let addr = try!(sock.recv_from(&mut mut_buf));
+            Ok(addr.map(|addr| (len - mut_buf.remaining(), addr)))
+        }));
+        self.count_read(size);
+        Ok((size, addr))
+    }
+
+    /// Block on sending a datagram to `addr`
+    pub fn send_to(&mut self, buf : &[u8], addr : &SocketAddr) -> io::Result<usize> {
+        let size = try!(self.try_until(RW::Write, |sock| {
+            let mut slice_buf = SliceBuf::wrap(buf);
+            let sent = try!(sock.send_to(&mut slice_buf, addr));
+            Ok(sent.map(|_| buf.len() - slice_buf.remaining()))
+        }));
+        self.count_written(size);
+        Ok(size)
+    }
+
+    /// Set the default destination of `send()` and the only source of `recv()`
+    pub fn connect(&self, addr : &SocketAddr) -> io::Result<()> {
+        self.with_raw(|sock| connect_udp(sock, addr))
+    }
+
+    /// Block on receiving a datagram from the connected address
+    pub fn recv(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        let size = try!(self.try_until(RW::Read, |sock| {
+            sys::would_block(sys::cvt_size(unsafe {
+                libc::recv(sock.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void,
+                           buf.len() as libc::size_t, 0)
+            }))
+        }));
+        self.count_read(size);
+        Ok(size)
+    }
+
+    /// Block on sending a datagram to the connected address
+    pub fn send(&mut self, buf : &[u8]) -> io::Result<usize> {
+        let size = try!(self.try_until(RW::Write, |sock| {
+            sys::would_block(sys::cvt_size(unsafe {
+                libc::send(sock.as_raw_fd(), buf.as_ptr() as *const libc::c_void,
+                           buf.len() as libc::size_t, 0)
+            }))
+        }));
+        self.count_written(size);
+        Ok(size)
+    }
+
+    /// Join multicast group `addr`
+    pub fn join_multicast(&self, addr : &IpAddr) -> io::Result<()> {
+        self.with_raw(|sock| sock.join_multicast(&mio_ip_addr(addr)))
+    }
+
+    /// Leave multicast group `addr`
+    pub fn leave_multicast(&self, addr : &IpAddr) -> io::Result<()> {
+        self.with_raw(|sock| sock.leave_multicast(&mio_ip_addr(addr)))
+    }
+}
+
+/// `mio` counterpart of `addr`
+fn mio_ip_addr(addr : &IpAddr) -> mio::IpAddr {
+    match *addr {
+        IpAddr::V4(addr) => mio::IpAddr::V4(addr),
+        IpAddr::V6(addr) => mio::IpAddr::V6(addr),
+    }
+}
@@ -0,0 +1,300 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Child processes with cooperative stdio pipes
+//!
+//! Pipes of a spawned child are wrapped in the current coroutine, and
+//! `Child::wait()` blocks only the coroutine, waking up on `SIGCHLD`.
+//! `SIGCHLD` is blocked in the `mioco` thread from the first spawn on, and
+//! received by `mioco` itself, so don't create another `SignalFd` for it.
+//!
+//! Other threads that don't block `SIGCHLD` may take it instead (see
+//! `mioco::signal`), so waiting coroutines also check their children every
+//! 100 milliseconds.
+
+use std::ffi::OsStr;
+use std::io::{self, Read};
+use std::os::unix::io::{RawFd, FromRawFd, IntoRawFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process;
+
+use libc;
+use mio::unix::{PipeReader, PipeWriter};
+
+use super::{MiocoHandle, RefCoroutine, TypedEventSource, current_handle};
+use super::signal::{self, Signal, SignalFd};
+use super::sys;
+use super::thread_pool;
+use super::timerfd::TimerFd;
+
+pub use std::process::Stdio;
+
+/// How often `Child::wait()` checks the child if no `SIGCHLD` was received
+const CHILD_POLL_MS : u64 = 100;
+
+/// Standard input of a child process
+pub type ChildStdin = TypedEventSource<PipeWriter>;
+
+/// Standard output of a child process
+pub type ChildStdout = TypedEventSource<PipeReader>;
+
+/// Standard error of a child process
+pub type ChildStderr = TypedEventSource<PipeReader>;
+
+/// Builder for spawning a child process
+///
+/// Mirrors `std::process::Command`, but all the standard streams
+/// default to `Stdio::piped()`.
+pub struct Command {
+    inn : process::Command,
+}
+
+impl Command {
+    /// Create a builder for running `program`
+    pub fn new<S : AsRef<OsStr>>(program : S) -> Command {
+        let mut inn = process::Command::new(program);
+        inn.stdin(Stdio::piped());
+        inn.stdout(Stdio::piped());
+        inn.stderr(Stdio::piped());
+
+        Command {
+            inn: inn,
+        }
+    }
+
+    /// Add an argument
+    pub fn arg<S : AsRef<OsStr>>(&mut self, arg : S) -> &mut Command {
+        self.inn.arg(arg);
+        self
+    }
+
+    /// Add multiple arguments
+    pub fn args<S : AsRef<OsStr>>(&mut self, args : &[S]) -> &mut Command {
+        self.inn.args(args);
+        self
+    }
+
+    /// Set an environment variable
+    pub fn env<K : AsRef<OsStr>, V : AsRef<OsStr>>(&mut self, key : K, val : V) -> &mut Command {
+        self.inn.env(key, val);
+        self
+    }
+
+    /// Set working directory
+    pub fn current_dir<P : AsRef<Path>>(&mut self, dir : P) -> &mut Command {
+        self.inn.current_dir(dir);
+        self
+    }
+
+    /// Configure standard input
+    pub fn stdin(&mut self, cfg : Stdio) -> &mut Command {
+        self.inn.stdin(cfg);
+        self
+    }
+
+    /// Configure standard output
+    pub fn stdout(&mut self, cfg : Stdio) -> &mut Command {
+        self.inn.stdout(cfg);
+        self
+    }
+
+    /// Configure standard error
+    pub fn stderr(&mut self, cfg : Stdio) -> &mut Command {
+        self.inn.stderr(cfg);
+        self
+    }
+
+    /// Schedule a closure to be run in the child just before `exec`
+    ///
+    /// See `std::os::unix::process::CommandExt::before_exec()`.
+    pub fn before_exec<F>(&mut self, f : F) -> &mut Command
+        where F : FnMut() -> io::Result<()> + Send + Sync + 'static {
+        self.inn.before_exec(f);
+        self
+    }
+
+    /// Spawn the child, wrapping its pipes in the current coroutine
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        let mut mioco = current_handle();
+        // `SIGCHLD` must be blocked before the child has a chance to exit
+        try!(watch_sigchld(&mioco.coroutine));
+        let mut child = try!(self.inn.spawn());
+
+        let stdin = match child.stdin.take() {
+            Some(pipe) => Some(mioco.wrap(try!(pipe_from::<PipeWriter>(pipe.into_raw_fd())))),
+            None => None,
+        };
+        let stdout = match child.stdout.take() {
+            Some(pipe) => Some(mioco.wrap(try!(pipe_from::<PipeReader>(pipe.into_raw_fd())))),
+            None => None,
+        };
+        let stderr = match child.stderr.take() {
+            Some(pipe) => Some(mioco.wrap(try!(pipe_from::<PipeReader>(pipe.into_raw_fd())))),
+            None => None,
+        };
+
+        Ok(Child {
+            pid: child.id() as libc::pid_t,
+            stdin: stdin,
+            stdout: stdout,
+            stderr: stderr,
+            status: None,
+        })
+    }
+}
+
+/// Make sure `Server` receives `SIGCHLD`
+fn watch_sigchld(coroutine : &RefCoroutine) -> io::Result<()> {
+    let co = coroutine.borrow();
+    let mut shared = co.server_shared.borrow_mut();
+    if shared.sigchld.is_none() {
+        shared.sigchld = Some(try!(SignalFd::new(&[signal::SIGCHLD])));
+    }
+    Ok(())
+}
+
+fn pipe_from<T : FromRawFd>(fd : RawFd) -> io::Result<T> {
+    let pipe = unsafe { T::from_raw_fd(fd) };
+    try!(sys::set_nonblock(fd));
+    Ok(pipe)
+}
+
+/// Exit status of a child process
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExitStatus(libc::c_int);
+
+impl ExitStatus {
+    /// Did the process exit with code 0
+    pub fn success(&self) -> bool {
+        self.code() == Some(0)
+    }
+
+    /// Exit code, if the process exited normally
+    pub fn code(&self) -> Option<i32> {
+        if self.0 & 0x7f == 0 {
+            Some((self.0 >> 8) & 0xff)
+        } else {
+            None
+        }
+    }
+
+    /// Signal that terminated the process, if any
+    pub fn signal(&self) -> Option<Signal> {
+        let sig = self.0 & 0x7f;
+        if sig != 0 && sig != 0x7f {
+            Some(sig)
+        } else {
+            None
+        }
+    }
+}
+
+/// Spawned child process
+///
+/// Pipes left in `stdin`, `stdout` and `stderr` are released when `Child` is
+/// dropped, so `take()` the ones to keep using.
+pub struct Child {
+    pid : libc::pid_t,
+
+    /// Standard input, if piped
+    pub stdin : Option<ChildStdin>,
+    /// Standard output, if piped
+    pub stdout : Option<ChildStdout>,
+    /// Standard error, if piped
+    pub stderr : Option<ChildStderr>,
+
+    status : Option<ExitStatus>,
+}
+
+impl Child {
+    /// Process ID of the child
+    pub fn id(&self) -> u32 {
+        self.pid as u32
+    }
+
+    /// Send `signal` to the child
+    pub fn kill(&self, signal : Signal) -> io::Result<()> {
+        if self.status.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "process already exited"));
+        }
+        sys::cvt(unsafe { libc::kill(self.pid, signal) }).map(|_| ())
+    }
+
+    /// Check if the child exited, without blocking
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if self.status.is_some() {
+            return Ok(self.status);
+        }
+
+        let mut status = 0;
+        let pid = try!(sys::cvt(unsafe { libc::waitpid(self.pid, &mut status, libc::WNOHANG) }));
+        if pid != 0 {
+            self.status = Some(ExitStatus(status));
+        }
+
+        Ok(self.status)
+    }
+
+    /// Block until the child exits
+    ///
+    /// Any number of coroutines can wait for their children at the same time.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        if let Some(status) = try!(self.try_wait()) {
+            return Ok(status);
+        }
+
+        let mut mioco = current_handle();
+        let timer = try!(TimerFd::new());
+        try!(timer.set_periodic(CHILD_POLL_MS));
+        let timer = mioco.wrap(timer);
+
+        let res = self.wait_polling(&mut mioco, &timer);
+        timer.release();
+        res
+    }
+
+    /// Block until the child exits, checking it on `SIGCHLD` and every time `timer` expires
+    fn wait_polling(&mut self, mioco : &mut MiocoHandle, timer : &TypedEventSource<TimerFd>) -> io::Result<ExitStatus> {
+        let mut buf = [0u8; 64];
+        let mut waiting = false;
+
+        loop {
+            // Every `SIGCHLD` wakes up all the waiters, and completion pipe
+            // might contain leftover notifications, so check `waitpid` after
+            // every wakeup.
+            if let Some(status) = try!(self.try_wait()) {
+                return Ok(status);
+            }
+
+            let (mut reader, writer) = try!(thread_pool::completion(&mioco.coroutine));
+            // don't pile up waiters while no `SIGCHLD` arrives
+            if !waiting {
+                mioco.coroutine.borrow().server_shared.borrow_mut().child_waiters.push(writer);
+                waiting = true;
+            }
+
+            let event = try!(mioco.select_read_from(&[reader.index(), timer.index()]));
+            if event.index() == timer.index() {
+                try!(timer.with_raw(|timer| timer.try_read()));
+            } else {
+                try!(reader.read(&mut buf));
+                waiting = false;
+            }
+        }
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        if let Some(ref stdin) = self.stdin {
+            stdin.release();
+        }
+        if let Some(ref stdout) = self.stdout {
+            stdout.release();
+        }
+        if let Some(ref stderr) = self.stderr {
+            stderr.release();
+        }
+    }
+}
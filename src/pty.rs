@@ -0,0 +1,181 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Pseudo-terminals
+//!
+//! The master side of a `Pty` is handled cooperatively, like any other
+//! event source, while a child process spawned with `Pty::spawn()` gets
+//! the slave side as its controlling terminal and standard streams.
+
+use std::io::{self, Read, Write};
+use std::ptr;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+
+use libc;
+use mio;
+
+use super::{LazyEventSource, EventSourceIndex, Traffic};
+use super::process::{Command, Child, Stdio};
+use super::sys;
+
+/// Size of a terminal window, in characters
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WindowSize {
+    /// Number of rows
+    pub rows : u16,
+    /// Number of columns
+    pub cols : u16,
+}
+
+/// Pseudo-terminal pair
+///
+/// Reading and writing accesses the master side. Reads report end of
+/// file only after the slave side is closed in every process, including
+/// this one - see `close_slave()`.
+pub struct Pty {
+    master : LazyEventSource<mio::Io>,
+    slave : Option<mio::Io>,
+}
+
+impl Pty {
+    /// Open new pseudo-terminal pair
+    pub fn open() -> io::Result<Pty> {
+        Pty::open_impl(None)
+    }
+
+    /// Open new pseudo-terminal pair with a given window size
+    pub fn open_with_size(size : WindowSize) -> io::Result<Pty> {
+        Pty::open_impl(Some(size))
+    }
+
+    fn open_impl(size : Option<WindowSize>) -> io::Result<Pty> {
+        let winsize = size.map(to_winsize);
+        let winp = match winsize {
+            Some(ref winsize) => winsize as *const libc::winsize,
+            None => ptr::null(),
+        };
+
+        let mut master = -1;
+        let mut slave = -1;
+        try!(sys::cvt(unsafe {
+            sys::openpty(&mut master, &mut slave, ptr::null_mut(), ptr::null(), winp)
+        }));
+
+        let master_io = unsafe { mio::Io::from_raw_fd(master) };
+        let slave_io = unsafe { mio::Io::from_raw_fd(slave) };
+
+        try!(sys::set_cloexec(master));
+        try!(sys::set_cloexec(slave));
+        try!(sys::set_nonblock(master));
+
+        Ok(Pty {
+            master: LazyEventSource::new(master_io),
+            slave: Some(slave_io),
+        })
+    }
+
+    /// Spawn `cmd` attached to the slave side
+    ///
+    /// The child is started in a new session, with the pseudo-terminal as
+    /// its controlling terminal and all the standard streams. `cmd` is taken
+    /// by value, as its standard streams are replaced and a hook starting the
+    /// session is added, so it could not be reused for another `Pty` anyway.
+    pub fn spawn(&self, mut cmd : Command) -> io::Result<Child> {
+        cmd.stdin(try!(self.slave_stdio()));
+        cmd.stdout(try!(self.slave_stdio()));
+        cmd.stderr(try!(self.slave_stdio()));
+        cmd.before_exec(|| {
+            try!(sys::cvt(unsafe { libc::setsid() }));
+            try!(sys::cvt(unsafe {
+                libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY as _, 0)
+            }));
+            Ok(())
+        });
+
+        cmd.spawn()
+    }
+
+    fn slave_stdio(&self) -> io::Result<Stdio> {
+        let slave = match self.slave {
+            Some(ref slave) => slave,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "slave side is closed")),
+        };
+        let fd = try!(sys::cvt(unsafe { libc::dup(slave.as_raw_fd()) }));
+        Ok(unsafe { Stdio::from_raw_fd(fd) })
+    }
+
+    /// Close the slave side in this process
+    ///
+    /// Call it after spawning the children, so reading the master side
+    /// reports end of file once they all exit.
+    pub fn close_slave(&mut self) {
+        self.slave = None;
+    }
+
+    /// Get window size
+    pub fn window_size(&self) -> io::Result<WindowSize> {
+        let mut winsize = to_winsize(WindowSize { rows: 0, cols: 0 });
+        let winp = &mut winsize as *mut libc::winsize;
+        try!(self.master.with_raw(|io| sys::cvt(unsafe {
+            libc::ioctl(io.as_raw_fd(), libc::TIOCGWINSZ as _, winp)
+        })));
+
+        Ok(WindowSize {
+            rows: winsize.ws_row,
+            cols: winsize.ws_col,
+        })
+    }
+
+    /// Set window size
+    ///
+    /// The foreground process group of the terminal receives `SIGWINCH`.
+    pub fn set_window_size(&self, size : WindowSize) -> io::Result<()> {
+        let winsize = to_winsize(size);
+        self.master.with_raw(|io| sys::cvt(unsafe {
+            libc::ioctl(io.as_raw_fd(), libc::TIOCSWINSZ as _, &winsize as *const libc::winsize)
+        })).map(|_| ())
+    }
+
+    /// Index identificator of the master side, for `select`-like operations
+    ///
+    /// Registers the master side in the current coroutine.
+    pub fn index(&self) -> EventSourceIndex {
+        self.master.index()
+    }
+
+    /// Traffic of the master side
+    ///
+    /// See `TypedEventSource::traffic()`.
+    pub fn traffic(&self) -> Traffic {
+        self.master.traffic()
+    }
+}
+
+fn to_winsize(size : WindowSize) -> libc::winsize {
+    libc::winsize {
+        ws_row: size.rows,
+        ws_col: size.cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+impl Read for Pty {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        match self.master.with_source(|source| source.read(buf)) {
+            // Linux reports `EIO` once all the slave fds are closed
+            Err(ref e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            res => res,
+        }
+    }
+}
+
+impl Write for Pty {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        self.master.with_source(|source| source.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
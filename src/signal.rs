@@ -0,0 +1,155 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Unix signals as event sources
+//!
+//! Create `SignalFd` for signals you're interested in and `wrap` it in a
+//! coroutine. Then block on `read_signal()`, or use it in `select`-like
+//! operations along with other event sources:
+//!
+//! ```ignore
+//! let mut signals = mioco.wrap(try!(SignalFd::new(&[SIGTERM, SIGHUP])));
+//!
+//! loop {
+//!     match try!(signals.read_signal()) {
+//!         SIGHUP => reload(),
+//!         _ => break,
+//!     }
+//! }
+//! ```
+//!
+//! Signal mask is per-thread, and a signal sent to the process is delivered to
+//! any thread that does not block it. `SignalFd` blocks its signals only in the
+//! calling thread (and `mioco` helper threads block all of them), so for
+//! process-wide signals to reliably reach `SignalFd`, they must be blocked in
+//! every other thread of the process too. The easiest way is to create `SignalFd`
+//! (or block the signals with `pthread_sigmask()`) in the main thread, before any
+//! other threads are spawned, as they inherit the mask.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+
+use libc;
+use mio;
+
+use super::{TypedEventSource, RW};
+use super::sys;
+
+pub use libc::{SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2, SIGCHLD, SIGPIPE, SIGALRM, SIGWINCH};
+
+/// Signal number
+pub type Signal = libc::c_int;
+
+/// Source of Unix signals based on Linux `signalfd`
+///
+/// Signals are blocked in the calling thread, so signals directed to this
+/// thread are not handled by default handlers anymore and are delivered
+/// through `SignalFd`. Signals sent to the whole process reach it only if they
+/// are blocked in every thread; see the module documentation.
+///
+/// Once the last `SignalFd` of a signal is dropped, the signal is unblocked
+/// again, unless it was blocked before. Drop it in the thread that created it.
+pub struct SignalFd {
+    io : mio::Io,
+    signals : Vec<Signal>,
+}
+
+impl_evented!(SignalFd, io);
+
+/// Blocked signals of `SignalFd`s in the current thread
+struct BlockedSignal {
+    /// Number of `SignalFd`s receiving the signal
+    count : usize,
+    /// Not blocked before the first `SignalFd`, so to be unblocked after the last one
+    unblock : bool,
+}
+
+thread_local!(static BLOCKED_SIGNALS : RefCell<HashMap<Signal, BlockedSignal>> = RefCell::new(HashMap::new()));
+
+impl SignalFd {
+    /// Create `SignalFd` receiving `signals`
+    pub fn new(signals : &[Signal]) -> io::Result<SignalFd> {
+        let mut set : libc::sigset_t = unsafe { mem::zeroed() };
+        let mut old_set : libc::sigset_t = unsafe { mem::zeroed() };
+        unsafe { sys::sigemptyset(&mut set) };
+        for &signal in signals {
+            try!(sys::cvt(unsafe { sys::sigaddset(&mut set, signal) }));
+        }
+
+        let fd = try!(sys::cvt(unsafe {
+            sys::signalfd(-1, &set, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC)
+        }));
+        let io = unsafe { mio::Io::from_raw_fd(fd) };
+
+        let err = unsafe { sys::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old_set) };
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(err));
+        }
+
+        BLOCKED_SIGNALS.with(|blocked| {
+            let mut blocked = blocked.borrow_mut();
+            for &signal in signals {
+                let entry = blocked.entry(signal).or_insert(BlockedSignal {
+                    count: 0,
+                    unblock: unsafe { sys::sigismember(&old_set, signal) } == 0,
+                });
+                entry.count += 1;
+            }
+        });
+
+        Ok(SignalFd {
+            io: io,
+            signals: signals.to_vec(),
+        })
+    }
+
+    /// Read next pending signal, or `None` if there's none
+    pub fn try_read_signal(&self) -> io::Result<Option<Signal>> {
+        // `struct signalfd_siginfo` starting with `uint32_t ssi_signo`
+        let mut info = [0u32; sys::SIGNALFD_SIGINFO_SIZE / 4];
+        let size = try!(sys::would_block(sys::cvt_size(unsafe {
+            libc::read(self.io.as_raw_fd(), info.as_mut_ptr() as *mut libc::c_void,
+                       sys::SIGNALFD_SIGINFO_SIZE as libc::size_t)
+        })));
+
+        Ok(size.map(|_| info[0] as Signal))
+    }
+}
+
+impl Drop for SignalFd {
+    fn drop(&mut self) {
+        let mut set : libc::sigset_t = unsafe { mem::zeroed() };
+        unsafe { sys::sigemptyset(&mut set) };
+
+        BLOCKED_SIGNALS.with(|blocked| {
+            let mut blocked = blocked.borrow_mut();
+            for signal in &self.signals {
+                let last = match blocked.get_mut(signal) {
+                    Some(entry) => {
+                        entry.count -= 1;
+                        entry.count == 0
+                    },
+                    None => continue,
+                };
+                if last {
+                    if blocked.remove(signal).unwrap().unblock {
+                        unsafe { sys::sigaddset(&mut set, *signal) };
+                    }
+                }
+            }
+        });
+
+        unsafe { sys::pthread_sigmask(libc::SIG_UNBLOCK, &set, ptr::null_mut()) };
+    }
+}
+
+impl TypedEventSource<SignalFd> {
+    /// Block on receiving a signal
+    pub fn read_signal(&mut self) -> io::Result<Signal> {
+        self.try_until(RW::Read, |signals| signals.try_read_signal())
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Standard streams of the current process
+//!
+//! Pipes, sockets and terminals are set non-blocking and handled
+//! cooperatively, like any other event source. Redirected regular files and
+//! devices like `/dev/null` can't be polled, but never block for long either,
+//! so they're accessed directly. Closed streams behave like empty ones.
+//!
+//! Non-blocking flag belongs to the open file description, which is shared
+//! with every process using the same terminal or pipe, and with
+//! `std::io::stdout()` and friends in this process. While a wrapper exists,
+//! `println!` and the like can fail with `WouldBlock` (and panic), and so can
+//! other programs, eg. the parent shell or a child spawned with inherited
+//! stdio. Keep wrappers short-lived and write through them only.
+//!
+//! Original flags are restored when the wrapper is dropped, when the process
+//! exits, is terminated by `SIGINT`, `SIGTERM` or `SIGHUP` (unless a handler
+//! was installed for them before), and when `mioco` tears down coroutines
+//! after a shutdown deadline. `SIGKILL` and other fatal signals can't be
+//! handled, so they leave the flags changed.
+//!
+//! Create only one wrapper per stream at a time.
+
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::{RawFd, AsRawFd, FromRawFd};
+use std::sync::{Once, ONCE_INIT};
+use std::sync::atomic::{AtomicIsize, Ordering, ATOMIC_ISIZE_INIT};
+
+use libc;
+use mio;
+
+use super::{LazyEventSource, EventSourceIndex};
+use super::sys;
+
+enum Kind {
+    Evented(LazyEventSource<mio::Io>),
+    Plain(mio::Io),
+    Closed,
+}
+
+struct StdStream {
+    kind : Kind,
+    /// Standard fd, whose original flags are to be restored
+    restore_flags : Option<RawFd>,
+}
+
+/// Original status flags of standard streams set non-blocking, plus one;
+/// zero if not changed
+static ORIGINAL_FLAGS : [AtomicIsize; 3] = [ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT, ATOMIC_ISIZE_INIT];
+
+/// Installs process-wide restoring of `ORIGINAL_FLAGS`
+static RESTORE_ON_EXIT : Once = ONCE_INIT;
+
+/// Signals terminating the process by default, handled by `restore_on_signal()`
+const RESTORE_SIGNALS : [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// Restore original status flags of standard streams
+///
+/// Called automatically (see the module documentation), but can be useful eg.
+/// before leaving the process in a way that skips `atexit` handlers.
+pub fn restore_flags() {
+    for fd in 0..ORIGINAL_FLAGS.len() {
+        restore_fd_flags(fd as RawFd);
+    }
+}
+
+/// Restore original status flags of standard stream `fd`, if they were changed
+///
+/// Async-signal-safe.
+fn restore_fd_flags(fd : RawFd) {
+    let flags = ORIGINAL_FLAGS[fd as usize].swap(0, Ordering::SeqCst);
+    if flags != 0 {
+        unsafe { libc::fcntl(fd, libc::F_SETFL, (flags - 1) as libc::c_int) };
+    }
+}
+
+extern "C" fn restore_at_exit() {
+    restore_flags();
+}
+
+extern "C" fn restore_on_signal(signal : libc::c_int) {
+    restore_flags();
+    unsafe {
+        sys::signal(signal, libc::SIG_DFL);
+        sys::raise(signal);
+    }
+}
+
+/// Restore flags on exit and on default-fatal signals, unless they're handled already
+fn install_restore_on_exit() {
+    RESTORE_ON_EXIT.call_once(|| {
+        unsafe {
+            sys::atexit(restore_at_exit);
+            for &signal in &RESTORE_SIGNALS {
+                let handler = restore_on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                let old = sys::signal(signal, handler);
+                if old != libc::SIG_DFL {
+                    sys::signal(signal, old);
+                }
+            }
+        }
+    });
+}
+
+impl StdStream {
+    fn new(fd : RawFd) -> io::Result<StdStream> {
+        let dup = match sys::cvt(unsafe { libc::dup(fd) }) {
+            Ok(dup) => dup,
+            Err(ref e) if e.raw_os_error() == Some(libc::EBADF) => {
+                return Ok(StdStream {
+                    kind: Kind::Closed,
+                    restore_flags: None,
+                })
+            },
+            Err(e) => return Err(e),
+        };
+
+        let io = unsafe { mio::Io::from_raw_fd(dup) };
+        try!(sys::set_cloexec(dup));
+
+        if !try!(is_pollable(dup)) {
+            return Ok(StdStream {
+                kind: Kind::Plain(io),
+                restore_flags: None,
+            });
+        }
+
+        install_restore_on_exit();
+        let flags = try!(sys::set_nonblock(dup));
+        // keep flags recorded by a wrapper that still exists
+        ORIGINAL_FLAGS[fd as usize].compare_and_swap(0, flags as isize + 1, Ordering::SeqCst);
+
+        Ok(StdStream {
+            kind: Kind::Evented(LazyEventSource::new(io)),
+            restore_flags: Some(fd),
+        })
+    }
+
+    fn index(&self) -> Option<EventSourceIndex> {
+        match self.kind {
+            Kind::Evented(ref inn) => Some(inn.index()),
+            _ => None,
+        }
+    }
+
+    fn is_tty(&self) -> bool {
+        match self.kind {
+            Kind::Evented(ref inn) => inn.with_raw(|io| is_tty(io)),
+            Kind::Plain(ref io) => is_tty(io),
+            Kind::Closed => false,
+        }
+    }
+}
+
+impl Drop for StdStream {
+    fn drop(&mut self) {
+        if let Some(fd) = self.restore_flags {
+            restore_fd_flags(fd);
+        }
+    }
+}
+
+fn is_tty(io : &mio::Io) -> bool {
+    unsafe { libc::isatty(io.as_raw_fd()) == 1 }
+}
+
+/// Can `fd` be registered in `epoll`
+fn is_pollable(fd : RawFd) -> io::Result<bool> {
+    const S_IFMT : libc::mode_t = 0o170000;
+    const S_IFIFO : libc::mode_t = 0o010000;
+    const S_IFCHR : libc::mode_t = 0o020000;
+    const S_IFSOCK : libc::mode_t = 0o140000;
+
+    let mut stat : libc::stat = unsafe { mem::zeroed() };
+    try!(sys::cvt(unsafe { libc::fstat(fd, &mut stat) }));
+
+    Ok(match stat.st_mode & S_IFMT {
+        S_IFIFO | S_IFSOCK => true,
+        S_IFCHR => unsafe { libc::isatty(fd) == 1 },
+        _ => false,
+    })
+}
+
+/// Standard input of the process
+pub struct Stdin {
+    inn : StdStream,
+}
+
+/// Standard output of the process
+pub struct Stdout {
+    inn : StdStream,
+}
+
+/// Standard error of the process
+pub struct Stderr {
+    inn : StdStream,
+}
+
+/// Wrap standard input of the process
+pub fn stdin() -> io::Result<Stdin> {
+    Ok(Stdin { inn: try!(StdStream::new(libc::STDIN_FILENO)) })
+}
+
+/// Wrap standard output of the process
+pub fn stdout() -> io::Result<Stdout> {
+    Ok(Stdout { inn: try!(StdStream::new(libc::STDOUT_FILENO)) })
+}
+
+/// Wrap standard error of the process
+pub fn stderr() -> io::Result<Stderr> {
+    Ok(Stderr { inn: try!(StdStream::new(libc::STDERR_FILENO)) })
+}
+
+impl Stdin {
+    /// Index identificator, for `select`-like operations
+    ///
+    /// `None` if the stream can't be polled (eg. a redirected file), and is
+    /// always ready. Registers the stream in the current coroutine.
+    pub fn index(&self) -> Option<EventSourceIndex> {
+        self.inn.index()
+    }
+
+    /// Is the stream a terminal
+    pub fn is_tty(&self) -> bool {
+        self.inn.is_tty()
+    }
+}
+
+impl Read for Stdin {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        match self.inn.kind {
+            Kind::Evented(ref inn) => inn.with_source(|source| source.read(buf)),
+            Kind::Plain(ref mut io) => io.read(buf),
+            Kind::Closed => Ok(0),
+        }
+    }
+}
+
+fn write(stream : &mut StdStream, buf : &[u8]) -> io::Result<usize> {
+    match stream.kind {
+        Kind::Evented(ref inn) => inn.with_source(|source| source.write(buf)),
+        Kind::Plain(ref mut io) => io.write(buf),
+        Kind::Closed => Err(io::Error::new(io::ErrorKind::BrokenPipe, "stream is closed")),
+    }
+}
+
+impl Stdout {
+    /// Index identificator, for `select`-like operations
+    ///
+    /// See `Stdin::index()`.
+    pub fn index(&self) -> Option<EventSourceIndex> {
+        self.inn.index()
+    }
+
+    /// Is the stream a terminal
+    pub fn is_tty(&self) -> bool {
+        self.inn.is_tty()
+    }
+}
+
+impl Write for Stdout {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        write(&mut self.inn, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Stderr {
+    /// Index identificator, for `select`-like operations
+    ///
+    /// See `Stdin::index()`.
+    pub fn index(&self) -> Option<EventSourceIndex> {
+        self.inn.index()
+    }
+
+    /// Is the stream a terminal
+    pub fn is_tty(&self) -> bool {
+        self.inn.is_tty()
+    }
+}
+
+impl Write for Stderr {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        write(&mut self.inn, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Supervisors restarting failed coroutines
+//!
+//! `Supervisor` runs child coroutines created by factory functions, and
+//! restarts the ones that return an error or panic. If they fail too often,
+//! the supervisor gives up, stops the remaining children and returns an
+//! error. Supervisors can be run as children of other supervisors, which
+//! makes such escalation restart a whole subtree:
+//!
+//! ```ignore
+//! let mut workers = Supervisor::new(Strategy::OneForOne);
+//! workers.child(|mioco| worker(mioco)).child(|mioco| worker(mioco));
+//!
+//! let mut root = Supervisor::new(Strategy::OneForAll);
+//! root.child(move |mioco| workers.run(mioco)).child(|mioco| listener(mioco));
+//!
+//! try!(root.run(mioco));
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+use super::{MiocoHandle, RefCoroutine, State, cancel, cancelled_error, is_cancelled};
+use super::sys;
+use super::thread_pool;
+
+/// What to restart when a child fails
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Restart only the failed child
+    OneForOne,
+    /// Stop all the other children, and restart all of them
+    OneForAll,
+}
+
+type Factory = Rc<Fn(&mut MiocoHandle) -> io::Result<()>>;
+
+/// Supervisor of child coroutines
+pub struct Supervisor {
+    strategy : Strategy,
+    max_restarts : usize,
+    period_ms : u64,
+    factories : Vec<Factory>,
+}
+
+impl Supervisor {
+    /// Create a supervisor with no children
+    ///
+    /// By default it gives up after more than 3 restarts within 5 seconds.
+    pub fn new(strategy : Strategy) -> Supervisor {
+        Supervisor {
+            strategy: strategy,
+            max_restarts: 3,
+            period_ms: 5000,
+            factories: Vec::new(),
+        }
+    }
+
+    /// Give up after more than `max_restarts` restarts within `period_ms` milliseconds
+    pub fn intensity(&mut self, max_restarts : usize, period_ms : u64) -> &mut Supervisor {
+        self.max_restarts = max_restarts;
+        self.period_ms = period_ms;
+        self
+    }
+
+    /// Add a child, started by calling `f`
+    ///
+    /// `f` is called again on every restart.
+    pub fn child<F>(&mut self, f : F) -> &mut Supervisor
+        where F : Fn(&mut MiocoHandle) -> io::Result<()> + 'static {
+        self.factories.push(Rc::new(f));
+        self
+    }
+
+    /// Run the children, blocking the current coroutine until they all finish
+    ///
+    /// Children finishing successfully are not restarted. Fails when the
+    /// restart intensity is exceeded, waiting for the children fails, or the
+    /// current coroutine is cancelled, after stopping all the children.
+    pub fn run(&self, mioco : &MiocoHandle) -> io::Result<()> {
+        let (mut reader, writer) = try!(thread_pool::completion(&mioco.coroutine));
+
+        let spawn = |i : usize| -> RefCoroutine {
+            let factory = self.factories[i].clone();
+            let handle = mioco.spawn(move |mioco| factory(mioco));
+            handle.coroutine.borrow_mut().join_waker = Some(writer.clone());
+            handle.coroutine
+        };
+
+        let mut children : Vec<Option<RefCoroutine>> = (0..self.factories.len()).map(|i| Some(spawn(i))).collect();
+        let mut restarts = VecDeque::new();
+        // Stopping all the children for `Strategy::OneForAll` restart
+        let mut restarting = false;
+        // Stopping all the children before giving up
+        let mut give_up : Option<io::Error> = None;
+        let mut was_cancelled = false;
+        // Waiting for the children failed once already
+        let mut wait_failed = false;
+
+        loop {
+            let mut failed = Vec::new();
+            for (i, child) in children.iter_mut().enumerate() {
+                let finished = child.as_ref().map_or(false, |co| co.borrow().state == State::Finished);
+                if !finished {
+                    continue;
+                }
+
+                let co = child.take().unwrap();
+                let res = co.borrow_mut().result.take().unwrap_or_else(|| {
+                    Err(io::Error::new(io::ErrorKind::Other, "coroutine panicked"))
+                });
+                if let Err(e) = res {
+                    if !restarting && give_up.is_none() && !was_cancelled {
+                        warn!("Supervisor: child {} failed: {}", i, e);
+                        failed.push(i);
+                    }
+                }
+            }
+
+            if !failed.is_empty() {
+                let now = sys::precise_time_ns() / 1000_000;
+                for _ in &failed {
+                    restarts.push_back(now);
+                }
+                while restarts.front().map_or(false, |&t| t + self.period_ms < now) {
+                    restarts.pop_front();
+                }
+
+                if restarts.len() > self.max_restarts {
+                    error!("Supervisor: restart intensity exceeded, giving up");
+                    give_up = Some(io::Error::new(io::ErrorKind::Other, "supervisor restart intensity exceeded"));
+                    stop_all(&children);
+                } else {
+                    match self.strategy {
+                        Strategy::OneForOne => for i in failed {
+                            children[i] = Some(spawn(i));
+                        },
+                        Strategy::OneForAll => {
+                            restarting = true;
+                            stop_all(&children);
+                        },
+                    }
+                }
+            }
+
+            let running = children.iter().any(|child| child.is_some());
+
+            if restarting && !running && !was_cancelled {
+                restarting = false;
+                for i in 0..children.len() {
+                    children[i] = Some(spawn(i));
+                }
+                continue;
+            }
+
+            if !running {
+                break;
+            }
+
+            let mut buf = [0u8; 64];
+            match reader.read(&mut buf) {
+                Err(ref e) if is_cancelled(e) => {
+                    // Children must be stopped even if the supervisor is
+                    // cancelled, so stop blocking operations from failing
+                    was_cancelled = true;
+                    mioco.coroutine.borrow_mut().cancelled = false;
+                    stop_all(&children);
+                },
+                Err(e) => {
+                    if wait_failed {
+                        // can't wait for the children anymore
+                        return Err(e);
+                    }
+                    error!("Supervisor: waiting for children failed: {}, giving up", e);
+                    wait_failed = true;
+                    if give_up.is_none() {
+                        give_up = Some(e);
+                    }
+                    stop_all(&children);
+                },
+                Ok(_) => {},
+            }
+        }
+
+        if was_cancelled {
+            mioco.coroutine.borrow_mut().cancelled = true;
+            return Err(cancelled_error());
+        }
+
+        match give_up {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Cancel all the running children
+fn stop_all(children : &[Option<RefCoroutine>]) {
+    for child in children {
+        if let Some(ref co) = *child {
+            cancel(co);
+        }
+    }
+}
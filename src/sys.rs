@@ -0,0 +1,228 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Low-level unix helpers and FFI bindings not (yet) covered by `libc`
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::os::unix::io::RawFd;
+
+use libc::{self, c_int, c_void, socklen_t, ssize_t};
+use nix;
+
+/// Implement `mio::Evented` for a struct by delegating to its `mio::Io` field
+macro_rules! impl_evented {
+    ($t:ty, $field:ident) => {
+        impl ::mio::Evented for $t {
+            fn register(&self, selector : &mut ::mio::Selector, token : ::mio::Token,
+                        interest : ::mio::EventSet, opts : ::mio::PollOpt) -> ::std::io::Result<()> {
+                ::mio::Evented::register(&self.$field, selector, token, interest, opts)
+            }
+
+            fn reregister(&self, selector : &mut ::mio::Selector, token : ::mio::Token,
+                          interest : ::mio::EventSet, opts : ::mio::PollOpt) -> ::std::io::Result<()> {
+                ::mio::Evented::reregister(&self.$field, selector, token, interest, opts)
+            }
+
+            fn deregister(&self, selector : &mut ::mio::Selector) -> ::std::io::Result<()> {
+                ::mio::Evented::deregister(&self.$field, selector)
+            }
+        }
+    }
+}
+
+/// Convert `-1` returned by libc call to `io::Error`
+pub fn cvt(ret : c_int) -> io::Result<c_int> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+/// Like `cvt`, for calls returning `ssize_t`
+pub fn cvt_size(ret : ssize_t) -> io::Result<usize> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+/// Convert `WouldBlock` error into `Ok(None)`, like `mio::TryRead` does
+pub fn would_block<T>(res : io::Result<T>) -> io::Result<Option<T>> {
+    match res {
+        Ok(t) => Ok(Some(t)),
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Convert `nix` error to `io::Error`
+pub fn from_nix(err : nix::Error) -> io::Error {
+    match err {
+        nix::Error::Sys(errno) => io::Error::from_raw_os_error(errno as i32),
+        nix::Error::InvalidPath => io::Error::new(io::ErrorKind::InvalidInput, "invalid path"),
+    }
+}
+
+/// `getsockopt` of a plain value
+#[cfg(target_os = "linux")]
+pub fn getsockopt<T : Copy>(fd : RawFd, level : c_int, name : c_int) -> io::Result<T> {
+    unsafe {
+        let mut val : T = mem::zeroed();
+        let mut len = mem::size_of::<T>() as socklen_t;
+        try!(cvt(libc::getsockopt(fd, level, name, &mut val as *mut T as *mut c_void, &mut len)));
+        Ok(val)
+    }
+}
+
+/// Take pending error of a socket (`SO_ERROR`), eg. after non-blocking `connect`
+#[cfg(target_os = "linux")]
+pub fn take_socket_error(fd : RawFd) -> io::Result<()> {
+    let err : c_int = try!(getsockopt(fd, libc::SOL_SOCKET, libc::SO_ERROR));
+    if err == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(err))
+    }
+}
+
+/// Set `O_NONBLOCK` on `fd`, returning its previous status flags
+pub fn set_nonblock(fd : RawFd) -> io::Result<c_int> {
+    let flags = try!(cvt(unsafe { libc::fcntl(fd, libc::F_GETFL) }));
+    try!(cvt(unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) }));
+    Ok(flags)
+}
+
+/// Set `FD_CLOEXEC` on `fd`
+pub fn set_cloexec(fd : RawFd) -> io::Result<()> {
+    let flags = try!(cvt(unsafe { libc::fcntl(fd, libc::F_GETFD) }));
+    cvt(unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) }).map(|_| ())
+}
+
+/// Read an 8-byte counter, like the ones of `timerfd` and `eventfd`
+#[cfg(target_os = "linux")]
+pub fn read_u64(fd : RawFd) -> io::Result<Option<u64>> {
+    let mut val = 0u64;
+    would_block(cvt_size(unsafe {
+        libc::read(fd, &mut val as *mut u64 as *mut c_void, 8)
+    })).map(|res| res.map(|_| val))
+}
+
+/// Add to an 8-byte counter of `eventfd`
+#[cfg(target_os = "linux")]
+pub fn write_u64(fd : RawFd, val : u64) -> io::Result<Option<()>> {
+    would_block(cvt_size(unsafe {
+        libc::write(fd, &val as *const u64 as *const c_void, 8)
+    })).map(|res| res.map(|_| ()))
+}
+
+#[cfg(target_os = "linux")]
+fn cmsg_align(len : usize) -> usize {
+    let align = mem::size_of::<usize>();
+    (len + align - 1) & !(align - 1)
+}
+
+/// Size of control message buffer able to hold `fds` file descriptors (`CMSG_SPACE`)
+#[cfg(target_os = "linux")]
+pub fn cmsg_space_fds(fds : usize) -> usize {
+    let cmsghdr_size = mem::size_of::<usize>() + 2 * mem::size_of::<c_int>();
+    cmsg_align(cmsghdr_size) + cmsg_align(fds * mem::size_of::<c_int>())
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(non_camel_case_types)]
+pub struct ucred {
+    pub pid : libc::pid_t,
+    pub uid : libc::uid_t,
+    pub gid : libc::gid_t,
+}
+
+#[cfg(target_os = "linux")]
+extern {
+    pub fn accept4(fd : c_int, addr : *mut libc::sockaddr, len : *mut socklen_t, flags : c_int) -> c_int;
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct itimerspec {
+    pub it_interval : libc::timespec,
+    pub it_value : libc::timespec,
+}
+
+#[cfg(target_os = "linux")]
+extern {
+    pub fn timerfd_create(clockid : c_int, flags : c_int) -> c_int;
+    pub fn timerfd_settime(fd : c_int, flags : c_int,
+                           new_value : *const itimerspec,
+                           old_value : *mut itimerspec) -> c_int;
+}
+
+/// Monotonic time in nanoseconds
+pub fn precise_time_ns() -> u64 {
+    let mut ts : libc::timespec = unsafe { mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1000_000_000 + ts.tv_nsec as u64
+}
+
+#[cfg(target_os = "linux")]
+extern {
+    pub fn eventfd(initval : libc::c_uint, flags : c_int) -> c_int;
+}
+
+/// Size of `struct signalfd_siginfo`
+#[cfg(target_os = "linux")]
+pub const SIGNALFD_SIGINFO_SIZE : usize = 128;
+
+#[cfg(target_os = "linux")]
+extern {
+    pub fn signalfd(fd : c_int, mask : *const libc::sigset_t, flags : c_int) -> c_int;
+}
+
+extern {
+    pub fn pthread_sigmask(how : c_int, set : *const libc::sigset_t, oldset : *mut libc::sigset_t) -> c_int;
+    pub fn sigemptyset(set : *mut libc::sigset_t) -> c_int;
+    pub fn sigaddset(set : *mut libc::sigset_t, signum : c_int) -> c_int;
+    pub fn sigfillset(set : *mut libc::sigset_t) -> c_int;
+    pub fn sigismember(set : *const libc::sigset_t, signum : c_int) -> c_int;
+}
+
+extern {
+    pub fn signal(signum : c_int, handler : libc::sighandler_t) -> libc::sighandler_t;
+    pub fn raise(signum : c_int) -> c_int;
+    pub fn atexit(cb : extern "C" fn()) -> c_int;
+}
+
+/// Block all signals in the calling thread
+///
+/// Used in helper threads, so signals are delivered to `mioco` threads.
+pub fn block_all_signals() {
+    unsafe {
+        let mut set : libc::sigset_t = mem::zeroed();
+        sigfillset(&mut set);
+        pthread_sigmask(libc::SIG_BLOCK, &set, ptr::null_mut());
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[link(name = "util")]
+extern {
+    pub fn openpty(amaster : *mut c_int, aslave : *mut c_int, name : *mut libc::c_char,
+                   termp : *const c_void, winp : *const libc::winsize) -> c_int;
+}
+
+/// Size of `struct inotify_event` without the name
+#[cfg(target_os = "linux")]
+pub const INOTIFY_EVENT_SIZE : usize = 16;
+
+#[cfg(target_os = "linux")]
+extern {
+    pub fn inotify_init1(flags : c_int) -> c_int;
+    pub fn inotify_add_watch(fd : c_int, pathname : *const libc::c_char, mask : u32) -> c_int;
+    pub fn inotify_rm_watch(fd : c_int, wd : c_int) -> c_int;
+}
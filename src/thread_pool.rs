@@ -0,0 +1,140 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Thread pool for operations that have no asynchronous counterpart
+//!
+//! Jobs are executed in a separate thread while the `Coroutine` that
+//! submitted them is blocked on a pipe, just like on any other
+//! `TypedEventSource`. This way, eg. file IO does not stall the whole
+//! `EventLoop`.
+
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+
+use mio::unix::{self, PipeReader, PipeWriter};
+use nix;
+
+use super::{RefCoroutine, TypedEventSource, wrap_impl};
+use super::sys;
+
+/// Number of threads started in `ThreadPool`
+pub const DEFAULT_SIZE : usize = 4;
+
+type Job = Box<FnMut() + Send>;
+
+/// Handle to a pool of threads executing blocking jobs
+///
+/// Threads finish when all the handles are dropped.
+#[derive(Clone)]
+pub struct ThreadPool {
+    tx : Sender<Job>,
+}
+
+impl ThreadPool {
+    pub fn new(size : usize) -> Self {
+        let (tx, rx) = channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+
+        for _ in 0..size {
+            let rx = rx.clone();
+            thread::spawn(move || {
+                // eg. `SIGCHLD` must reach `SignalFd` in `mioco` thread
+                sys::block_all_signals();
+                loop {
+                    let job = rx.lock().unwrap().recv();
+                    match job {
+                        Ok(mut job) => job(),
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+
+        ThreadPool {
+            tx: tx,
+        }
+    }
+
+    fn execute<F>(&self, f : F)
+        where F : FnOnce() + Send + 'static {
+            let mut f = Some(f);
+            let job : Job = Box::new(move || (f.take().unwrap())());
+            self.tx.send(job).expect("thread pool threads are gone");
+        }
+}
+
+/// Pipe waking up `Coroutine` after its job in `ThreadPool` is done
+///
+/// Stored in `Coroutine` by the index of the reading end, as keeping
+/// `TypedEventSource` there would create an `Rc`-cycle.
+pub struct Completion {
+    index : usize,
+    writer : Arc<PipeWriter>,
+}
+
+/// Get (creating if needed) `Completion` of a `coroutine`
+///
+/// Also used to wake up a `Coroutine` when its children finish.
+pub fn completion(coroutine : &RefCoroutine) -> io::Result<(TypedEventSource<PipeReader>, Arc<PipeWriter>)> {
+    let existing = coroutine.borrow().completion.as_ref().map(|c| (c.index, c.writer.clone()));
+
+    let (index, writer) = match existing {
+        Some(existing) => existing,
+        None => {
+            let (reader, writer) = try!(unix::pipe());
+            let reader = wrap_impl(coroutine, reader);
+            reader.inn.borrow_mut().internal = true;
+            let completion = Completion {
+                index: reader.index().as_usize(),
+                writer: Arc::new(writer),
+            };
+            let res = (completion.index, completion.writer.clone());
+            coroutine.borrow_mut().completion = Some(completion);
+            res
+        }
+    };
+
+    let reader = TypedEventSource {
+        inn: coroutine.borrow().source(index).unwrap(),
+        _t: PhantomData,
+    };
+
+    Ok((reader, writer))
+}
+
+/// Execute `f` in the `ThreadPool` blocking `coroutine` until it's finished
+pub fn offload<F, T>(coroutine : &RefCoroutine, f : F) -> io::Result<T>
+where F : FnOnce() -> io::Result<T> + Send + 'static,
+      T : Send + 'static {
+    let (mut reader, writer) = try!(completion(coroutine));
+
+    let pool = {
+        let co = coroutine.borrow();
+        let mut shared = co.server_shared.borrow_mut();
+        shared.thread_pool()
+    };
+
+    let result = Arc::new(Mutex::new(None));
+
+    {
+        let result = result.clone();
+        pool.execute(move || {
+            *result.lock().unwrap() = Some(f());
+            let _ = nix::unistd::write(writer.as_raw_fd(), &[0u8]);
+        });
+    }
+
+    // Pipe might contain leftover notifications, so don't trust
+    // the wakeup itself - check the result.
+    let mut buf = [0u8; 64];
+    loop {
+        if let Some(res) = result.lock().unwrap().take() {
+            return res;
+        }
+        try!(reader.read(&mut buf));
+    }
+}
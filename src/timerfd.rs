@@ -0,0 +1,94 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Kernel timers usable as `mio` event sources
+//!
+//! Arm `TimerFd` and `wrap` it in a coroutine. Then block on `wait()`,
+//! or use it in `select`-like operations along with other event sources.
+
+use std::io;
+use std::ptr;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+
+use libc;
+use mio;
+
+use super::{TypedEventSource, RW};
+use super::sys;
+
+/// Timer based on Linux `timerfd`
+///
+/// Becomes readable when it expires.
+pub struct TimerFd {
+    io : mio::Io,
+}
+
+impl_evented!(TimerFd, io);
+
+impl TimerFd {
+    /// Create new, disarmed timer
+    pub fn new() -> io::Result<TimerFd> {
+        let fd = try!(sys::cvt(unsafe {
+            sys::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC)
+        }));
+
+        Ok(TimerFd {
+            io: unsafe { mio::Io::from_raw_fd(fd) },
+        })
+    }
+
+    /// Arm the timer to expire once, after `ms` milliseconds
+    pub fn set_oneshot(&self, ms : u64) -> io::Result<()> {
+        // zero would disarm the timer
+        let value = if ms == 0 { ms_to_timespec(0, 1) } else { ms_to_timespec(ms, 0) };
+        self.set(value, ms_to_timespec(0, 0))
+    }
+
+    /// Arm the timer to expire every `ms` milliseconds
+    pub fn set_periodic(&self, ms : u64) -> io::Result<()> {
+        if ms == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "zero timer period"));
+        }
+        self.set(ms_to_timespec(ms, 0), ms_to_timespec(ms, 0))
+    }
+
+    /// Disarm the timer
+    ///
+    /// Also clears expirations that were not read yet.
+    pub fn disarm(&self) -> io::Result<()> {
+        self.set(ms_to_timespec(0, 0), ms_to_timespec(0, 0))
+    }
+
+    /// Read number of expirations since the last read, or `None` if
+    /// the timer has not expired
+    pub fn try_read(&self) -> io::Result<Option<u64>> {
+        sys::read_u64(self.io.as_raw_fd())
+    }
+
+    fn set(&self, value : libc::timespec, interval : libc::timespec) -> io::Result<()> {
+        let spec = sys::itimerspec {
+            it_interval: interval,
+            it_value: value,
+        };
+
+        sys::cvt(unsafe {
+            sys::timerfd_settime(self.io.as_raw_fd(), 0, &spec, ptr::null_mut())
+        }).map(|_| ())
+    }
+}
+
+fn ms_to_timespec(ms : u64, ns : u64) -> libc::timespec {
+    libc::timespec {
+        tv_sec: (ms / 1000) as libc::time_t,
+        tv_nsec: ((ms % 1000) * 1000_000 + ns) as libc::c_long,
+    }
+}
+
+impl TypedEventSource<TimerFd> {
+    /// Block until the timer expires
+    ///
+    /// Returns number of expirations since the last read.
+    pub fn wait(&mut self) -> io::Result<u64> {
+        self.try_until(RW::Read, |timer| timer.try_read())
+    }
+}
@@ -0,0 +1,500 @@
+// Copyright 2015 Dawid Ciężarkiewicz <dpc@dpc.pw>
+// See LICENSE-MPL2 file for more information.
+
+//! Unix domain sockets for `mioco` coroutines
+//!
+//! Just like types in `mioco::net`, these register themselves in the
+//! coroutine that uses them first, and so connecting, blocking operations and
+//! `index()` panic when called outside of `mioco` coroutine.
+
+use std::ffi::OsStr;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{RawFd, AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+
+use libc::{self, c_int, socklen_t};
+use mio;
+use mio::unix as mio_unix;
+use nix::sys::socket::{sendmsg, recvmsg, ControlMessage, MsgFlags, MSG_CMSG_CLOEXEC};
+use nix::sys::uio::IoVec;
+
+use super::{TypedEventSource, LazyEventSource, EventSourceIndex, Traffic, RW};
+use super::sys;
+
+/// Address of a Unix domain socket
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SocketAddr {
+    /// Socket not bound to any address
+    Unnamed,
+    /// Socket bound to a filesystem path
+    Pathname(PathBuf),
+    /// Socket bound to a name in Linux abstract namespace (without leading null byte)
+    Abstract(Vec<u8>),
+}
+
+fn sun_path_offset() -> usize {
+    mem::size_of::<libc::sa_family_t>()
+}
+
+impl SocketAddr {
+    fn to_raw(&self) -> io::Result<(libc::sockaddr_un, socklen_t)> {
+        let mut addr : libc::sockaddr_un = unsafe { mem::zeroed() };
+        addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+        let (bytes, offset, terminator) = match *self {
+            SocketAddr::Unnamed => return Ok((addr, sun_path_offset() as socklen_t)),
+            SocketAddr::Pathname(ref path) => (path.as_os_str().as_bytes(), 0, 1),
+            SocketAddr::Abstract(ref name) => (&name[..], 1, 0),
+        };
+
+        if offset + bytes.len() + terminator > addr.sun_path.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "unix socket address too long"));
+        }
+
+        for (dst, src) in addr.sun_path[offset..].iter_mut().zip(bytes.iter()) {
+            *dst = *src as libc::c_char;
+        }
+
+        let len = sun_path_offset() + offset + bytes.len() + terminator;
+        Ok((addr, len as socklen_t))
+    }
+
+    fn from_raw(addr : &libc::sockaddr_un, len : socklen_t) -> SocketAddr {
+        let path_len = (len as usize).saturating_sub(sun_path_offset());
+        let path : Vec<u8> = addr.sun_path[..path_len].iter().map(|&c| c as u8).collect();
+
+        if path.is_empty() {
+            SocketAddr::Unnamed
+        } else if path[0] == 0 {
+            SocketAddr::Abstract(path[1..].to_vec())
+        } else {
+            let end = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+            SocketAddr::Pathname(PathBuf::from(OsStr::from_bytes(&path[..end])))
+        }
+    }
+}
+
+/// Credentials of the process on the other end of a socket (`SO_PEERCRED`)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    /// Process ID
+    pub pid : libc::pid_t,
+    /// User ID
+    pub uid : libc::uid_t,
+    /// Group ID
+    pub gid : libc::gid_t,
+}
+
+fn socket(ty : c_int) -> io::Result<RawFd> {
+    sys::cvt(unsafe { libc::socket(libc::AF_UNIX, ty | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, 0) })
+}
+
+fn socketpair(ty : c_int) -> io::Result<(RawFd, RawFd)> {
+    let mut fds = [0, 0];
+    try!(sys::cvt(unsafe {
+        libc::socketpair(libc::AF_UNIX, ty | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, 0, fds.as_mut_ptr())
+    }));
+    Ok((fds[0], fds[1]))
+}
+
+fn bind(fd : RawFd, addr : &SocketAddr) -> io::Result<()> {
+    let (addr, len) = try!(addr.to_raw());
+    sys::cvt(unsafe {
+        libc::bind(fd, &addr as *const _ as *const libc::sockaddr, len)
+    }).map(|_| ())
+}
+
+/// Returns `false` if the connection is still in progress
+fn connect(fd : RawFd, addr : &SocketAddr) -> io::Result<bool> {
+    let (addr, len) = try!(addr.to_raw());
+    match sys::cvt(unsafe {
+        libc::connect(fd, &addr as *const _ as *const libc::sockaddr, len)
+    }) {
+        Ok(_) => Ok(true),
+        Err(ref e) if e.raw_os_error() == Some(libc::EINPROGRESS) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn addr_of(fd : RawFd, peer : bool) -> io::Result<SocketAddr> {
+    let mut addr : libc::sockaddr_un = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_un>() as socklen_t;
+    try!(sys::cvt(unsafe {
+        let addr = &mut addr as *mut _ as *mut libc::sockaddr;
+        if peer {
+            libc::getpeername(fd, addr, &mut len)
+        } else {
+            libc::getsockname(fd, addr, &mut len)
+        }
+    }));
+    Ok(SocketAddr::from_raw(&addr, len))
+}
+
+fn peer_cred(fd : RawFd) -> io::Result<Credentials> {
+    let cred : sys::ucred = try!(sys::getsockopt(fd, libc::SOL_SOCKET, libc::SO_PEERCRED));
+    Ok(Credentials {
+        pid: cred.pid,
+        uid: cred.uid,
+        gid: cred.gid,
+    })
+}
+
+/// Unix domain socket listening for connections
+pub struct UnixListener {
+    inn : LazyEventSource<mio_unix::UnixListener>,
+}
+
+impl UnixListener {
+    /// Create a listener bound to filesystem `path`
+    pub fn bind<P : AsRef<Path>>(path : P) -> io::Result<UnixListener> {
+        UnixListener::bind_addr(&SocketAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Create a listener bound to `name` in the abstract namespace
+    pub fn bind_abstract(name : &[u8]) -> io::Result<UnixListener> {
+        UnixListener::bind_addr(&SocketAddr::Abstract(name.to_vec()))
+    }
+
+    /// Create a listener bound to `addr`
+    pub fn bind_addr(addr : &SocketAddr) -> io::Result<UnixListener> {
+        let fd = try!(socket(libc::SOCK_STREAM));
+        let listener = unsafe { mio_unix::UnixListener::from_raw_fd(fd) };
+        try!(bind(fd, addr));
+        try!(sys::cvt(unsafe { libc::listen(fd, 1024) }));
+
+        Ok(UnixListener {
+            inn: LazyEventSource::listener(listener),
+        })
+    }
+
+    /// Block on accepting a new connection
+    ///
+    /// Returned `UnixStream` is not registered in any coroutine yet, so it can
+    /// be moved to a newly spawned one. Fails once `mioco` is shutting down.
+    pub fn accept(&self) -> io::Result<(UnixStream, SocketAddr)> {
+        let (fd, addr) = try!(self.inn.with_source(|listener| {
+            listener.accept_until(|listener| {
+                let mut addr : libc::sockaddr_un = unsafe { mem::zeroed() };
+                let mut len = mem::size_of::<libc::sockaddr_un>() as socklen_t;
+                let fd = try!(sys::would_block(sys::cvt(unsafe {
+                    sys::accept4(listener.as_raw_fd(), &mut addr as *mut _ as *mut libc::sockaddr,
+                                 &mut len, libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC)
+                })));
+                Ok(fd.map(|fd| (fd, SocketAddr::from_raw(&addr, len))))
+            })
+        }));
+
+        Ok((UnixStream::from_fd(fd), addr))
+    }
+
+    /// Create a listener from a listening socket `fd`, eg. received with
+    /// `UnixStream::recv_fds()`
+    ///
+    /// Sets the `fd` non-blocking, and takes ownership of it on success.
+    pub unsafe fn from_fd(fd : RawFd) -> io::Result<UnixListener> {
+        try!(sys::set_nonblock(fd));
+        try!(sys::set_cloexec(fd));
+
+        Ok(UnixListener::from_raw_fd(fd))
+    }
+
+    /// Local address of the listener
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inn.with_raw(|listener| addr_of(listener.as_raw_fd(), false))
+    }
+
+    /// Index identificator, for `select`-like operations
+    ///
+    /// Registers the listener in the current coroutine. See `TypedEventSource::index()`.
+    pub fn index(&self) -> EventSourceIndex {
+        self.inn.index()
+    }
+}
+
+impl FromRawFd for UnixListener {
+    /// Take ownership of a listening socket `fd`, that is already non-blocking
+    ///
+    /// See `UnixListener::from_fd()`.
+    unsafe fn from_raw_fd(fd : RawFd) -> UnixListener {
+        UnixListener {
+            inn: LazyEventSource::listener(mio_unix::UnixListener::from_raw_fd(fd)),
+        }
+    }
+}
+
+/// Unix domain stream socket
+pub struct UnixStream {
+    inn : LazyEventSource<mio_unix::UnixStream>,
+}
+
+impl UnixStream {
+    fn from_fd(fd : RawFd) -> UnixStream {
+        UnixStream {
+            inn: LazyEventSource::new(unsafe { mio_unix::UnixStream::from_raw_fd(fd) }),
+        }
+    }
+
+    /// Connect to a socket bound to filesystem `path`
+    pub fn connect<P : AsRef<Path>>(path : P) -> io::Result<UnixStream> {
+        UnixStream::connect_addr(&SocketAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Connect to a socket bound to `name` in the abstract namespace
+    pub fn connect_abstract(name : &[u8]) -> io::Result<UnixStream> {
+        UnixStream::connect_addr(&SocketAddr::Abstract(name.to_vec()))
+    }
+
+    /// Connect to a socket bound to `addr`
+    ///
+    /// Blocks the current coroutine until the connection is established or fails.
+    /// Panics when called outside of `mioco` coroutine.
+    pub fn connect_addr(addr : &SocketAddr) -> io::Result<UnixStream> {
+        let fd = try!(socket(libc::SOCK_STREAM));
+        let stream = UnixStream::from_fd(fd);
+
+        if !try!(connect(fd, addr)) {
+            try!(stream.inn.with_source(|source| source.block_on(RW::Both)));
+            try!(sys::take_socket_error(fd));
+        }
+
+        Ok(stream)
+    }
+
+    /// Create a pair of connected sockets
+    pub fn pair() -> io::Result<(UnixStream, UnixStream)> {
+        let (a, b) = try!(socketpair(libc::SOCK_STREAM));
+        Ok((UnixStream::from_fd(a), UnixStream::from_fd(b)))
+    }
+
+    /// Remote address of the connection
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inn.with_raw(|stream| addr_of(stream.as_raw_fd(), true))
+    }
+
+    /// Local address of the connection
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inn.with_raw(|stream| addr_of(stream.as_raw_fd(), false))
+    }
+
+    /// Credentials of the process on the other end of the connection
+    pub fn peer_cred(&self) -> io::Result<Credentials> {
+        self.inn.with_raw(|stream| peer_cred(stream.as_raw_fd()))
+    }
+
+    /// Block on sending `buf` along with file descriptors `fds`
+    ///
+    /// See `TypedEventSource<UnixStream>::send_fds()`.
+    pub fn send_fds(&self, buf : &[u8], fds : &[RawFd]) -> io::Result<usize> {
+        self.inn.with_source(|stream| stream.send_fds(buf, fds))
+    }
+
+    /// Block on receiving data along with file descriptors
+    ///
+    /// See `TypedEventSource<UnixStream>::recv_fds()`.
+    pub fn recv_fds(&self, buf : &mut [u8], fds : &mut [RawFd]) -> io::Result<(usize, usize)> {
+        self.inn.with_source(|stream| stream.recv_fds(buf, fds))
+    }
+
+    /// Index identificator, for `select`-like operations
+    ///
+    /// Registers the stream in the current coroutine. See `TypedEventSource::index()`.
+    pub fn index(&self) -> EventSourceIndex {
+        self.inn.index()
+    }
+
+    /// Traffic of the connection
+    ///
+    /// See `TypedEventSource::traffic()`.
+    pub fn traffic(&self) -> Traffic {
+        self.inn.traffic()
+    }
+}
+
+impl Read for UnixStream {
+    fn read(&mut self, buf : &mut [u8]) -> io::Result<usize> {
+        self.inn.with_source(|stream| stream.read(buf))
+    }
+}
+
+impl Write for UnixStream {
+    fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+        self.inn.with_source(|stream| stream.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inn.with_source(|stream| stream.flush())
+    }
+}
+
+impl TypedEventSource<mio_unix::UnixStream> {
+    /// Block on sending `buf` along with file descriptors `fds` (`SCM_RIGHTS`)
+    ///
+    /// File descriptors are duplicated to the receiving process, so they
+    /// can be closed afterwards. Returns number of bytes sent.
+    pub fn send_fds(&mut self, buf : &[u8], fds : &[RawFd]) -> io::Result<usize> {
+        let size = try!(self.try_until(RW::Write, |stream| {
+            let iov = [IoVec::from_slice(buf)];
+            let cmsgs = [ControlMessage::ScmRights(fds)];
+            sys::would_block(sendmsg(stream.as_raw_fd(), &iov, &cmsgs, MsgFlags::empty(), None)
+                             .map_err(sys::from_nix))
+        }));
+        self.count_written(size);
+        Ok(size)
+    }
+
+    /// Block on receiving data into `buf` and up to `fds.len()` file descriptors
+    ///
+    /// Returns number of bytes and number of file descriptors received. Received
+    /// descriptors have `FD_CLOEXEC` set; ones that did not fit in `fds` are closed.
+    pub fn recv_fds(&mut self, buf : &mut [u8], fds : &mut [RawFd]) -> io::Result<(usize, usize)> {
+        let mut cmsg_buf = vec![0u8; sys::cmsg_space_fds(fds.len())];
+
+        let (size, received) = try!(self.try_until(RW::Read, |stream| {
+            let iov = [IoVec::from_mut_slice(&mut buf[..])];
+            let msg = match try!(sys::would_block(
+                    recvmsg(stream.as_raw_fd(), &iov, Some(&mut cmsg_buf), MSG_CMSG_CLOEXEC)
+                    .map_err(sys::from_nix))) {
+                Some(msg) => msg,
+                None => return Ok(None),
+            };
+
+            let mut received = 0;
+            for cmsg in msg.cmsgs() {
+                if let ControlMessage::ScmRights(received_fds) = cmsg {
+                    for &fd in received_fds {
+                        if received < fds.len() {
+                            fds[received] = fd;
+                            received += 1;
+                        } else {
+                            unsafe { libc::close(fd) };
+                        }
+                    }
+                }
+            }
+
+            Ok(Some((msg.bytes, received)))
+        }));
+        self.count_read(size);
+        Ok((size, received))
+    }
+}
+
+/// Unix domain datagram socket
+pub struct UnixDatagram {
+    inn : LazyEventSource<mio::Io>,
+}
+
+impl UnixDatagram {
+    fn from_fd(fd : RawFd) -> UnixDatagram {
+        UnixDatagram {
+            inn: LazyEventSource::new(unsafe { mio::Io::from_raw_fd(fd) }),
+        }
+    }
+
+    /// Create a socket bound to filesystem `path`
+    pub fn bind<P : AsRef<Path>>(path : P) -> io::Result<UnixDatagram> {
+        UnixDatagram::bind_addr(&SocketAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Create a socket bound to `name` in the abstract namespace
+    pub fn bind_abstract(name : &[u8]) -> io::Result<UnixDatagram> {
+        UnixDatagram::bind_addr(&SocketAddr::Abstract(name.to_vec()))
+    }
+
+    /// Create a socket bound to `addr`
+    pub fn bind_addr(addr : &SocketAddr) -> io::Result<UnixDatagram> {
+        let sock = try!(UnixDatagram::unbound());
+        try!(sock.inn.with_raw(|io| bind(io.as_raw_fd(), addr)));
+        Ok(sock)
+    }
+
+    /// Create a socket not bound to any address
+    pub fn unbound() -> io::Result<UnixDatagram> {
+        let fd = try!(socket(libc::SOCK_DGRAM));
+        Ok(UnixDatagram::from_fd(fd))
+    }
+
+    /// Create a pair of connected sockets
+    pub fn pair() -> io::Result<(UnixDatagram, UnixDatagram)> {
+        let (a, b) = try!(socketpair(libc::SOCK_DGRAM));
+        Ok((UnixDatagram::from_fd(a), UnixDatagram::from_fd(b)))
+    }
+
+    /// Set the default destination of `send()` and the only source of `recv()`
+    pub fn connect(&self, addr : &SocketAddr) -> io::Result<()> {
+        // datagram sockets connect right away
+        self.inn.with_raw(|io| connect(io.as_raw_fd(), addr).map(|_| ()))
+    }
+
+    /// Block on receiving a datagram
+    ///
+    /// Returns number of bytes received and the address of the sender.
+    pub fn recv_from(&self, buf : &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.inn.with_source(|source| {
+            let (size, addr) = try!(source.try_until(RW::Read, |io| {
+                let mut addr : libc::sockaddr_un = unsafe { mem::zeroed() };
+                let mut len = mem::size_of::<libc::sockaddr_un>() as socklen_t;
+                let size = try!(sys::would_block(sys::cvt_size(unsafe {
+                    libc::recvfrom(io.as_raw_fd(), buf.as_mut_ptr() as *mut libc::c_void,
+                                   buf.len() as libc::size_t, 0,
+                                   &mut addr as *mut _ as *mut libc::sockaddr, &mut len)
+                })));
+                Ok(size.map(|size| (size, SocketAddr::from_raw(&addr, len))))
+            }));
+            source.count_read(size);
+            Ok((size, addr))
+        })
+    }
+
+    /// Block on sending a datagram to `addr`
+    pub fn send_to(&self, buf : &[u8], addr : &SocketAddr) -> io::Result<usize> {
+        let (addr, len) = try!(addr.to_raw());
+        self.inn.with_source(|source| {
+            let size = try!(source.try_until(RW::Write, |io| {
+                sys::would_block(sys::cvt_size(unsafe {
+                    libc::sendto(io.as_raw_fd(), buf.as_ptr() as *const libc::c_void,
+                                 buf.len() as libc::size_t, 0,
+                                 &addr as *const _ as *const libc::sockaddr, len)
+                }))
+            }));
+            source.count_written(size);
+            Ok(size)
+        })
+    }
+
+    /// Block on receiving a datagram from the connected address
+    pub fn recv(&self, buf : &mut [u8]) -> io::Result<usize> {
+        self.inn.with_source(|source| source.read(buf))
+    }
+
+    /// Block on sending a datagram to the connected address
+    pub fn send(&self, buf : &[u8]) -> io::Result<usize> {
+        self.inn.with_source(|source| source.write(buf))
+    }
+
+    /// Local address of the socket
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inn.with_raw(|io| addr_of(io.as_raw_fd(), false))
+    }
+
+    /// Remote address of the connected socket
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inn.with_raw(|io| addr_of(io.as_raw_fd(), true))
+    }
+
+    /// Index identificator, for `select`-like operations
+    ///
+    /// Registers the socket in the current coroutine. See `TypedEventSource::index()`.
+    pub fn index(&self) -> EventSourceIndex {
+        self.inn.index()
+    }
+
+    /// Traffic of the socket
+    ///
+    /// See `TypedEventSource::traffic()`.
+    pub fn traffic(&self) -> Traffic {
+        self.inn.traffic()
+    }
+}
@@ -0,0 +1,115 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::rc::Rc;
+use std::thread;
+
+use mioco::MiocoHandle;
+use mioco::actor::{Actor, Reply, spawn_actor};
+
+enum Msg {
+    Add(u64),
+    Get(Reply<u64>),
+    /// Drop the `Reply` without replying
+    Ignore(Reply<u64>),
+    /// Finish the actor with an error
+    Fail(Reply<u64>),
+}
+
+struct Counter {
+    value : u64,
+    /// Set when the actor is dropped
+    dropped : Rc<Cell<bool>>,
+}
+
+impl Actor for Counter {
+    type Message = Msg;
+
+    fn handle(&mut self, _ : &mut MiocoHandle, msg : Msg) -> io::Result<()> {
+        match msg {
+            Msg::Add(n) => self.value += n,
+            Msg::Get(reply) => reply.send(self.value),
+            Msg::Ignore(_) => {},
+            Msg::Fail(_) => return Err(io::Error::new(io::ErrorKind::Other, "counter failed")),
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Counter {
+    fn drop(&mut self) {
+        self.dropped.set(true);
+    }
+}
+
+fn counter() -> (Counter, Rc<Cell<bool>>) {
+    let dropped = Rc::new(Cell::new(false));
+    (Counter { value: 0, dropped: dropped.clone() }, dropped)
+}
+
+#[test]
+fn messages_sent_from_coroutine_and_thread_are_handled() {
+    let value = Rc::new(Cell::new(0));
+    let (actor, dropped) = counter();
+
+    {
+        let value = value.clone();
+        mioco::start(move |mioco| {
+            let address = try!(spawn_actor(mioco, actor));
+            try!(address.send(Msg::Add(2)));
+
+            let remote = address.clone();
+            thread::spawn(move || {
+                remote.send(Msg::Add(10)).unwrap();
+            }).join().unwrap();
+
+            value.set(try!(address.call(mioco, Msg::Get)));
+            Ok(())
+        });
+    }
+
+    assert_eq!(value.get(), 12);
+    // finished once all the addresses were dropped
+    assert!(dropped.get());
+}
+
+#[test]
+fn dropped_reply_fails_the_call() {
+    let kind = Rc::new(Cell::new(None));
+    let (actor, _) = counter();
+
+    {
+        let kind = kind.clone();
+        mioco::start(move |mioco| {
+            let address = try!(spawn_actor(mioco, actor));
+            kind.set(address.call(mioco, Msg::Ignore).err().map(|e| e.kind()));
+            Ok(())
+        });
+    }
+
+    assert_eq!(kind.get(), Some(io::ErrorKind::BrokenPipe));
+}
+
+#[test]
+fn sending_to_finished_actor_fails() {
+    // (kind of error of the failing call, kind of error of the next send)
+    let kinds = Rc::new(RefCell::new((None, None)));
+    let (actor, dropped) = counter();
+
+    {
+        let kinds = kinds.clone();
+        mioco::start(move |mioco| {
+            let address = try!(spawn_actor(mioco, actor));
+            let call = address.call(mioco, Msg::Fail).err().map(|e| e.kind());
+            let send = address.send(Msg::Add(1)).err().map(|e| e.kind());
+            *kinds.borrow_mut() = (call, send);
+            Ok(())
+        });
+    }
+
+    assert_eq!(*kinds.borrow(), (Some(io::ErrorKind::BrokenPipe), Some(io::ErrorKind::BrokenPipe)));
+    assert!(dropped.get());
+}
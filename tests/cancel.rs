@@ -0,0 +1,103 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+
+use mioco::MiocoHandle;
+use mioco::timerfd::TimerFd;
+
+fn sleep(mioco : &mut MiocoHandle, ms : u64) -> io::Result<()> {
+    let timer = try!(TimerFd::new());
+    try!(timer.set_oneshot(ms));
+    try!(mioco.wrap(timer).wait());
+    Ok(())
+}
+
+#[test]
+fn blocked_coroutine_is_interrupted() {
+    // (error recognized as cancellation, seen by `is_cancelled()`, finished)
+    let results = Rc::new(Cell::new((false, false, false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let inner = results.clone();
+            let sleeper = mioco.spawn(move |mioco| {
+                let res = sleep(mioco, 60_000);
+                let cancelled = res.as_ref().err().map_or(false, mioco::is_cancelled);
+                inner.set((cancelled, mioco.is_cancelled(), false));
+                res
+            });
+
+            // let the sleeper block first
+            try!(sleep(mioco, 20));
+            sleeper.cancel();
+            try!(sleep(mioco, 20));
+
+            let (cancelled, seen, _) = results.get();
+            results.set((cancelled, seen, sleeper.is_finished()));
+            Ok(())
+        });
+    }
+
+    assert_eq!(results.get(), (true, true, true));
+}
+
+#[test]
+fn every_blocking_operation_fails_after_cancel() {
+    let failures = Rc::new(Cell::new(0));
+
+    {
+        let failures = failures.clone();
+        mioco::start(move |mioco| {
+            let worker = mioco.spawn(move |mioco| {
+                for _ in 0..3 {
+                    if sleep(mioco, 60_000).is_err() {
+                        failures.set(failures.get() + 1);
+                    }
+                }
+                Ok(())
+            });
+
+            // cancel through a token, before the worker even started
+            worker.cancel_token().cancel();
+            Ok(())
+        });
+    }
+
+    assert_eq!(failures.get(), 3);
+}
+
+#[test]
+fn cancelling_finished_coroutine_does_nothing() {
+    let results = Rc::new(Cell::new((0, false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let runs = Rc::new(Cell::new(0));
+            let child = {
+                let runs = runs.clone();
+                mioco.spawn(move |_| {
+                    runs.set(runs.get() + 1);
+                    Ok(())
+                })
+            };
+
+            try!(sleep(mioco, 20));
+            let finished = child.is_finished();
+            child.cancel();
+            child.cancel();
+
+            // the parent itself was not affected
+            try!(sleep(mioco, 1));
+            results.set((runs.get(), finished && !mioco.is_cancelled()));
+            Ok(())
+        });
+    }
+
+    assert_eq!(results.get(), (1, true));
+}
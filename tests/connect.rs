@@ -0,0 +1,114 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::Cell;
+use std::net::SocketAddr;
+use std::io::{Read, Write};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use mioco::net::{TcpListener, TcpStream};
+use mioco::timerfd::TimerFd;
+
+fn any_local_addr() -> SocketAddr {
+    FromStr::from_str("127.0.0.1:0").unwrap()
+}
+
+#[test]
+fn connect_in_a_loop_releases_sources() {
+    let done = Rc::new(Cell::new(0));
+
+    {
+        let done = done.clone();
+        mioco::start(move |_| {
+            let listener = try!(TcpListener::bind(&any_local_addr()));
+            let addr = try!(listener.local_addr());
+            // nothing listens there once the listener is dropped
+            let refused = try!(try!(TcpListener::bind(&any_local_addr())).local_addr());
+
+            // more than a coroutine can hold at once
+            for _ in 0..64 {
+                let stream = try!(TcpStream::connect_timeout(&addr, 1000));
+                drop(stream);
+                if TcpStream::connect_timeout(&refused, 1000).is_ok() {
+                    break;
+                }
+                done.set(done.get() + 1);
+            }
+
+            Ok(())
+        });
+    }
+
+    assert_eq!(done.get(), 64);
+}
+
+#[test]
+fn connected_stream_outlives_connecting_coroutine() {
+    let received = Rc::new(Cell::new(false));
+
+    {
+        let received = received.clone();
+        mioco::start(move |mioco| {
+            let listener = try!(TcpListener::bind(&any_local_addr()));
+            let addr = try!(listener.local_addr());
+
+            mioco.spawn(move |mioco| {
+                let mut stream = try!(TcpStream::connect(&addr));
+                mioco.spawn(move |mioco| {
+                    // let the connecting coroutine finish first
+                    let timer = try!(TimerFd::new());
+                    try!(timer.set_oneshot(50));
+                    try!(mioco.wrap(timer).wait());
+                    stream.write_all(b"hello")
+                });
+                Ok(())
+            });
+
+            let (mut conn, _) = try!(listener.accept());
+            let mut buf = Vec::new();
+            try!(conn.read_to_end(&mut buf));
+            received.set(&buf[..] == b"hello");
+
+            Ok(())
+        });
+    }
+
+    assert!(received.get());
+}
+
+#[test]
+fn listener_in_use_by_another_coroutine_fails() {
+    let results = Rc::new(Cell::new((false, false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let listener = Rc::new(try!(TcpListener::bind(&any_local_addr())));
+            let addr = try!(listener.local_addr());
+
+            {
+                let listener = listener.clone();
+                let results = results.clone();
+                mioco.spawn(move |_| {
+                    let accepted = listener.accept().is_ok();
+                    results.set((accepted, results.get().1));
+                    Ok(())
+                });
+            }
+
+            mioco.spawn(move |_| {
+                // the other coroutine is blocked in `accept()`
+                let failed = listener.accept().is_err();
+                results.set((results.get().0, failed));
+                try!(TcpStream::connect(&addr));
+                Ok(())
+            });
+
+            Ok(())
+        });
+    }
+
+    assert_eq!(results.get(), (true, true));
+}
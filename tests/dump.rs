@@ -0,0 +1,78 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+extern crate libc;
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::rc::Rc;
+
+use mioco::{Mioco, MiocoHandle, CoroutineState, RW};
+use mioco::signal::SIGQUIT;
+use mioco::timerfd::TimerFd;
+
+extern {
+    fn raise(signum : libc::c_int) -> libc::c_int;
+}
+
+fn sleep(mioco : &mut MiocoHandle, ms : u64) -> io::Result<()> {
+    let timer = try!(TimerFd::new());
+    try!(timer.set_oneshot(ms));
+    try!(mioco.wrap(timer).wait());
+    Ok(())
+}
+
+#[test]
+fn dump_describes_blocked_coroutine() {
+    let infos = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let infos = infos.clone();
+        mioco::start(move |mioco| {
+            let sleeper = mioco.spawn_named("sleeper", |mioco| sleep(mioco, 60_000));
+
+            // let the sleeper block first
+            try!(sleep(mioco, 20));
+            *infos.borrow_mut() = mioco.dump();
+            sleeper.cancel();
+            Ok(())
+        });
+    }
+
+    let infos = infos.borrow();
+    assert_eq!(infos.len(), 2);
+
+    assert_eq!(infos[0].id.as_usize(), 1);
+    assert_eq!(infos[0].state, CoroutineState::Running);
+    assert!(infos[0].blocked_on.is_empty());
+
+    let sleeper = &infos[1];
+    assert_eq!(sleeper.name, Some("sleeper".to_owned()));
+    assert_eq!(sleeper.state, CoroutineState::BlockedOn(RW::Read));
+    assert_eq!(sleeper.blocked_on.len(), 1);
+    assert!(sleeper.blocked_ms.is_some());
+    assert!(!sleeper.cancelled);
+    assert!(sleeper.to_string().starts_with("coroutine #2 (sleeper) [blocked on Read"));
+}
+
+#[test]
+fn sigquit_prints_dump_instead_of_terminating() {
+    let survived = Rc::new(Cell::new(false));
+
+    {
+        let survived = survived.clone();
+        let mut mioco = Mioco::new();
+        mioco.enable_sigquit_dump().unwrap();
+        mioco.start(move |mioco| {
+            // directed to this thread, where `SIGQUIT` is blocked now
+            unsafe { raise(SIGQUIT) };
+            try!(sleep(mioco, 50));
+            survived.set(true);
+            Ok(())
+        });
+    }
+
+    // the signal was consumed, so unblocking it with `Mioco` dropped did not
+    // terminate the process either
+    assert!(survived.get());
+}
@@ -0,0 +1,56 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::thread;
+
+use mioco::eventfd::EventFd;
+
+#[test]
+fn coroutine_woken_up_from_another_thread() {
+    let results = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let mut eventfd = mioco.wrap(try!(EventFd::new(0)));
+            let notifier = try!(eventfd.with_raw(|eventfd| eventfd.notifier()));
+
+            let thread = thread::spawn(move || {
+                thread::sleep_ms(50);
+                notifier.notify(3).unwrap();
+            });
+
+            results.borrow_mut().push(try!(eventfd.wait()));
+            // the counter was reset
+            let left = try!(eventfd.with_raw(|eventfd| eventfd.try_read()));
+            results.borrow_mut().push(left.unwrap_or(0));
+
+            thread.join().unwrap();
+            Ok(())
+        });
+    }
+
+    assert_eq!(*results.borrow(), vec![3, 0]);
+}
+
+#[test]
+fn semaphore_is_decremented_by_one() {
+    let results = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let results = results.clone();
+        mioco::start(move |_| {
+            let eventfd = try!(EventFd::semaphore(2));
+            try!(eventfd.notify(1));
+            for _ in 0..4 {
+                results.borrow_mut().push(try!(eventfd.try_read()));
+            }
+            Ok(())
+        });
+    }
+
+    assert_eq!(*results.borrow(), vec![Some(1), Some(1), Some(1), None]);
+}
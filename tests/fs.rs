@@ -0,0 +1,124 @@
+extern crate mioco;
+extern crate libc;
+
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fs;
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use mioco::fs::File;
+
+/// Fresh directory for a test, unique to the test process
+fn test_dir(name : &str) -> PathBuf {
+    let pid = unsafe { libc::getpid() };
+    let dir = env::temp_dir().join(format!("mioco-fs-{}-{}", name, pid));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn file_written_and_read_back() {
+    let dir = test_dir("rw");
+    let path = dir.join("file");
+    let data = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let path = path.clone();
+        let data = data.clone();
+        mioco::start(move |mioco| {
+            {
+                let mut file = try!(File::create(mioco, &path));
+                try!(file.write_all(b"hello world"));
+                try!(file.sync_all());
+            }
+
+            let mut file = try!(File::open(mioco, &path));
+            try!(file.seek(SeekFrom::Start(6)));
+            try!(file.read_to_end(&mut *data.borrow_mut()));
+            Ok(())
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(*data.borrow(), b"world".to_vec());
+}
+
+#[test]
+fn file_handed_over_to_another_coroutine() {
+    let dir = test_dir("handover");
+    let path = dir.join("file");
+    fs::File::create(&path).unwrap().write_all(b"hello").unwrap();
+    let results = Rc::new(Cell::new((0, 0)));
+
+    {
+        let path = path.clone();
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let file = try!(File::open(mioco, &path));
+
+            mioco.spawn(move |mioco| {
+                let mut file = file;
+                let mut data = Vec::new();
+                try!(file.read_to_end(&mut data));
+                results.set((data.len(), mioco.traffic().bytes_read));
+                Ok(())
+            });
+            Ok(())
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(results.get(), (5, 5));
+}
+
+#[test]
+fn metadata_and_read_dir() {
+    let dir = test_dir("meta");
+    fs::File::create(dir.join("a")).unwrap().write_all(b"abc").unwrap();
+    fs::create_dir(dir.join("b")).unwrap();
+    let results = Rc::new(RefCell::new((0, false, Vec::new())));
+
+    {
+        let dir = dir.clone();
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let len = try!(mioco::fs::metadata(mioco, dir.join("a"))).len();
+            let is_dir = try!(mioco::fs::metadata(mioco, dir.join("b"))).is_dir();
+
+            let mut names : Vec<String> = try!(mioco::fs::read_dir(mioco, &dir)).iter()
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+
+            *results.borrow_mut() = (len, is_dir, names);
+            Ok(())
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    let results = results.borrow();
+    assert_eq!(results.0, 3);
+    assert!(results.1);
+    assert_eq!(results.2, vec!["a".to_owned(), "b".to_owned()]);
+}
+
+#[test]
+fn opening_missing_file_fails() {
+    let dir = test_dir("missing");
+    let failed = Rc::new(Cell::new(false));
+
+    {
+        let path = dir.join("missing");
+        let failed = failed.clone();
+        mioco::start(move |mioco| {
+            failed.set(File::open(mioco, &path).is_err());
+            Ok(())
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(failed.get());
+}
@@ -0,0 +1,71 @@
+#![cfg(target_os = "linux")]
+
+#![feature(process_exec)]
+
+extern crate mioco;
+extern crate libc;
+
+use std::env;
+use std::net;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use mioco::net::{listeners_from_env, Listener};
+
+/// Set for the re-executed test binary, to the address of the passed listener
+const CHILD_ENV : &'static str = "MIOCO_TEST_LISTENERS_FROM_ENV";
+
+/// Runs in the re-executed test binary only
+#[test]
+fn listeners_from_env_child() {
+    let addr = match env::var(CHILD_ENV) {
+        Ok(addr) => addr,
+        Err(_) => return,
+    };
+
+    let listeners = listeners_from_env().unwrap();
+    assert_eq!(listeners.len(), 2);
+
+    match listeners[0] {
+        Listener::Tcp(ref listener) => assert_eq!(listener.local_addr().unwrap().to_string(), addr),
+        _ => panic!("fd 3 is not a TCP listener"),
+    }
+    match listeners[1] {
+        Listener::Other(fd) => assert_eq!(fd, 4),
+        _ => panic!("fd 4 is not left raw"),
+    }
+
+    assert!(env::var("LISTEN_PID").is_err());
+    assert!(env::var("LISTEN_FDS").is_err());
+}
+
+#[test]
+fn listeners_from_env_takes_inherited_fds() {
+    let tcp = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let udp = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = tcp.local_addr().unwrap();
+    let (tcp_fd, udp_fd) = (tcp.as_raw_fd(), udp.as_raw_fd());
+
+    let status = Command::new("/bin/sh")
+        .arg("-c")
+        // `exec` keeps the pid of the shell
+        .arg("LISTEN_PID=$$ LISTEN_FDS=2 exec \"$0\" listeners_from_env_child")
+        .arg(env::current_exe().unwrap())
+        .env(CHILD_ENV, addr.to_string())
+        .env("RUST_TEST_THREADS", "1")
+        .before_exec(move || {
+            // move out of the way first, in case either one is 3 or 4 already
+            let tcp_fd = unsafe { libc::fcntl(tcp_fd, libc::F_DUPFD, 10) };
+            let udp_fd = unsafe { libc::fcntl(udp_fd, libc::F_DUPFD, 10) };
+            if tcp_fd < 0 || udp_fd < 0 ||
+                unsafe { libc::dup2(tcp_fd, 3) } < 0 || unsafe { libc::dup2(udp_fd, 4) } < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        })
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+}
@@ -0,0 +1,48 @@
+extern crate mioco;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use mioco::CoroutineId;
+
+#[test]
+fn spawned_coroutines_have_unique_ids_and_names() {
+    // (id, name) as seen by the coroutines themselves, and ids of their `JoinHandle`s
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let handles = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let seen = seen.clone();
+        let handles = handles.clone();
+        mioco::start(move |mioco| {
+            seen.borrow_mut().push((mioco.id(), mioco.name()));
+
+            for name in &["first", "second"] {
+                let seen = seen.clone();
+                let handle = mioco.spawn_named(*name, move |mioco| {
+                    seen.borrow_mut().push((mioco.id(), mioco.name()));
+                    Ok(())
+                });
+                handles.borrow_mut().push(handle.id());
+            }
+
+            let seen = seen.clone();
+            let handle = mioco.spawn(move |mioco| {
+                seen.borrow_mut().push((mioco.id(), mioco.name()));
+                Ok(())
+            });
+            handles.borrow_mut().push(handle.id());
+            Ok(())
+        });
+    }
+
+    let mut seen = seen.borrow().clone();
+    seen.sort();
+    let ids : Vec<CoroutineId> = seen.iter().map(|&(id, _)| id).collect();
+    let names : Vec<Option<String>> = seen.iter().map(|&(_, ref name)| name.clone()).collect();
+
+    assert_eq!(ids.iter().map(|id| id.as_usize()).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    assert_eq!(&ids[1..], &handles.borrow()[..]);
+    assert_eq!(names, vec![None, Some("first".to_owned()), Some("second".to_owned()), None]);
+    assert_eq!(ids[1].to_string(), "#2");
+}
@@ -0,0 +1,211 @@
+extern crate mioco;
+
+use std::cell::{Cell, RefCell};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, IpAddr};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use mioco::net::{TcpListener, TcpStream, UdpSocket};
+
+fn any_local_addr() -> SocketAddr {
+    FromStr::from_str("127.0.0.1:0").unwrap()
+}
+
+#[test]
+fn tcp_echo() {
+    let results = Rc::new(RefCell::new((Vec::new(), false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let listener = try!(TcpListener::bind(&any_local_addr()));
+            let addr = try!(listener.local_addr());
+
+            mioco.spawn(move |_| {
+                let (mut conn, _) = try!(listener.accept());
+                let mut buf = [0u8; 1024];
+                loop {
+                    let size = try!(conn.read(&mut buf));
+                    if size == 0 {
+                        return Ok(());
+                    }
+                    try!(conn.write_all(&buf[..size]));
+                }
+            });
+
+            let mut stream = try!(TcpStream::connect(&addr));
+            let local = try!(stream.local_addr());
+            try!(stream.write_all(b"hello"));
+            let mut echoed = Vec::new();
+            while echoed.len() < 5 {
+                let mut buf = [0u8; 5];
+                let size = try!(stream.read(&mut buf));
+                if size == 0 {
+                    break;
+                }
+                echoed.extend(buf[..size].iter().cloned());
+            }
+
+            *results.borrow_mut() = (echoed, local.port() != 0);
+            Ok(())
+        });
+    }
+
+    let results = results.borrow();
+    assert_eq!(results.0, b"hello".to_vec());
+    assert!(results.1);
+}
+
+#[test]
+fn accepted_connection_handled_in_spawned_coroutine() {
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let received = received.clone();
+        mioco::start(move |mioco| {
+            let listener = try!(TcpListener::bind(&any_local_addr()));
+            let addr = try!(listener.local_addr());
+
+            mioco.spawn(move |mioco| {
+                for _ in 0..2 {
+                    let (mut conn, _) = try!(listener.accept());
+                    let received = received.clone();
+                    mioco.spawn(move |_| {
+                        let mut data = Vec::new();
+                        try!(conn.read_to_end(&mut data));
+                        received.borrow_mut().push(data);
+                        Ok(())
+                    });
+                }
+                Ok(())
+            });
+
+            for msg in &[&b"one"[..], &b"two"[..]] {
+                let mut stream = try!(TcpStream::connect(&addr));
+                try!(stream.write_all(msg));
+            }
+            Ok(())
+        });
+    }
+
+    let mut received = received.borrow().clone();
+    received.sort();
+    assert_eq!(received, vec![b"one".to_vec(), b"two".to_vec()]);
+}
+
+#[test]
+fn listener_handed_over_between_coroutines() {
+    let accepted = Rc::new(Cell::new(false));
+
+    {
+        let accepted = accepted.clone();
+        mioco::start(move |mioco| {
+            let listener = try!(TcpListener::bind(&any_local_addr()));
+            let addr = try!(listener.local_addr());
+
+            // register the listener in this coroutine first
+            let _ = listener.index();
+
+            mioco.spawn(move |_| {
+                try!(listener.accept());
+                accepted.set(true);
+                Ok(())
+            });
+
+            try!(TcpStream::connect(&addr));
+            Ok(())
+        });
+    }
+
+    assert!(accepted.get());
+}
+
+#[test]
+#[should_panic]
+fn connect_outside_of_coroutine_panics() {
+    let _ = TcpStream::connect(&any_local_addr());
+}
+
+#[test]
+fn udp_send_to_and_recv_from() {
+    let results = Rc::new(RefCell::new((Vec::new(), false, 0)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let server = try!(UdpSocket::bind(&any_local_addr()));
+            let server_addr = try!(server.local_addr());
+
+            mioco.spawn(move |_| {
+                let mut buf = [0u8; 64];
+                let (size, addr) = try!(server.recv_from(&mut buf));
+                try!(server.send_to(&buf[..size], &addr));
+                Ok(())
+            });
+
+            let client = try!(UdpSocket::bind(&any_local_addr()));
+            try!(client.send_to(b"ping", &server_addr));
+            let mut buf = [0u8; 64];
+            let (size, addr) = try!(client.recv_from(&mut buf));
+
+            *results.borrow_mut() = (buf[..size].to_vec(), addr == server_addr, client.traffic().bytes_read);
+            Ok(())
+        });
+    }
+
+    let results = results.borrow();
+    assert_eq!(results.0, b"ping".to_vec());
+    assert!(results.1);
+    assert_eq!(results.2, 4);
+}
+
+#[test]
+fn udp_connected_send_and_recv() {
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let received = received.clone();
+        mioco::start(move |_| {
+            let a = try!(UdpSocket::bind(&any_local_addr()));
+            let b = try!(UdpSocket::bind(&any_local_addr()));
+            let stranger = try!(UdpSocket::bind(&any_local_addr()));
+            try!(a.connect(&try!(b.local_addr())));
+            try!(b.connect(&try!(a.local_addr())));
+
+            // connected socket accepts datagrams only from its peer
+            try!(stranger.send_to(b"ignored", &try!(b.local_addr())));
+            try!(a.send(b"hello"));
+
+            let mut buf = [0u8; 64];
+            let size = try!(b.recv(&mut buf));
+            received.borrow_mut().extend(buf[..size].iter().cloned());
+            Ok(())
+        });
+    }
+
+    assert_eq!(*received.borrow(), b"hello".to_vec());
+}
+
+#[test]
+fn udp_multicast_membership() {
+    let results = Rc::new(Cell::new((false, false, false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |_| {
+            let group : IpAddr = FromStr::from_str("239.255.10.11").unwrap();
+            let sock = try!(UdpSocket::bind(&FromStr::from_str("0.0.0.0:0").unwrap()));
+
+            let joined = sock.join_multicast(&group).is_ok();
+            let left = sock.leave_multicast(&group).is_ok();
+            // there's no membership to drop anymore
+            let left_again = sock.leave_multicast(&group).is_ok();
+
+            results.set((joined, left, left_again));
+            Ok(())
+        });
+    }
+
+    assert_eq!(results.get(), (true, true, false));
+}
@@ -0,0 +1,92 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::Cell;
+use std::io::Read;
+use std::rc::Rc;
+
+use mioco::process::{Command, Stdio};
+
+fn sh(script : &str) -> Command {
+    let mut cmd = Command::new("/bin/sh");
+    cmd.arg("-c").arg(script);
+    cmd.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    cmd
+}
+
+#[test]
+fn children_waited_for_concurrently() {
+    let codes = Rc::new(Cell::new((None, None)));
+
+    {
+        let codes = codes.clone();
+        mioco::start(move |mioco| {
+            {
+                let codes = codes.clone();
+                mioco.spawn(move |_| {
+                    let mut child = try!(sh("sleep 0.2; exit 3").spawn());
+                    let code = try!(child.wait()).code();
+                    codes.set((code, codes.get().1));
+                    Ok(())
+                });
+            }
+
+            mioco.spawn(move |_| {
+                let mut child = try!(sh("exit 5").spawn());
+                let code = try!(child.wait()).code();
+                codes.set((codes.get().0, code));
+                Ok(())
+            });
+
+            Ok(())
+        });
+    }
+
+    assert_eq!(codes.get(), (Some(3), Some(5)));
+}
+
+#[test]
+fn dropped_children_release_pipes() {
+    let done = Rc::new(Cell::new(0));
+
+    {
+        let done = done.clone();
+        mioco::start(move |_| {
+            // more pipes than a coroutine can hold at once
+            for _ in 0..20 {
+                let mut child = try!(Command::new("/bin/echo").arg("hello").spawn());
+                let mut output = String::new();
+                try!(child.stdout.as_mut().unwrap().read_to_string(&mut output));
+                if output != "hello\n" || !try!(child.wait()).success() {
+                    break;
+                }
+                done.set(done.get() + 1);
+            }
+
+            Ok(())
+        });
+    }
+
+    assert_eq!(done.get(), 20);
+}
+
+#[test]
+fn child_waited_for_without_sigchld() {
+    // Main thread of the test harness does not block `SIGCHLD`, so the kernel
+    // may discard it before `mioco` has a chance to receive it
+    let code = Rc::new(Cell::new(None));
+
+    {
+        let code = code.clone();
+        mioco::start(move |_| {
+            for _ in 0..5 {
+                let mut child = try!(sh("exit 7").spawn());
+                code.set(try!(child.wait()).code());
+            }
+            Ok(())
+        });
+    }
+
+    assert_eq!(code.get(), Some(7));
+}
@@ -0,0 +1,56 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::RefCell;
+use std::io::Read;
+use std::rc::Rc;
+
+use mioco::process::Command;
+use mioco::pty::{Pty, WindowSize};
+
+#[test]
+fn output_of_spawned_echo_is_read() {
+    let results = Rc::new(RefCell::new((String::new(), false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |_| {
+            let mut pty = try!(Pty::open());
+            let mut cmd = Command::new("/bin/echo");
+            cmd.arg("hello");
+            let mut child = try!(pty.spawn(cmd));
+            pty.close_slave();
+
+            let mut output = String::new();
+            try!(pty.read_to_string(&mut output));
+            let success = try!(child.wait()).success();
+
+            *results.borrow_mut() = (output, success);
+            Ok(())
+        });
+    }
+
+    let results = results.borrow();
+    // terminal translates newlines
+    assert_eq!(results.0, "hello\r\n");
+    assert!(results.1);
+}
+
+#[test]
+fn window_size_is_set() {
+    let sizes = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let sizes = sizes.clone();
+        mioco::start(move |_| {
+            let pty = try!(Pty::open_with_size(WindowSize { rows: 24, cols: 80 }));
+            sizes.borrow_mut().push(try!(pty.window_size()));
+            try!(pty.set_window_size(WindowSize { rows: 50, cols: 132 }));
+            sizes.borrow_mut().push(try!(pty.window_size()));
+            Ok(())
+        });
+    }
+
+    assert_eq!(*sizes.borrow(), vec![WindowSize { rows: 24, cols: 80 }, WindowSize { rows: 50, cols: 132 }]);
+}
@@ -0,0 +1,119 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::RefCell;
+use std::io;
+use std::net::{self, SocketAddr};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::thread;
+
+use mioco::dns::Resolver;
+
+const TYPE_A : u16 = 1;
+
+fn read_u16(buf : &[u8], pos : usize) -> u16 {
+    (buf[pos] as u16) << 8 | buf[pos + 1] as u16
+}
+
+/// Answer `queries` queries: A of `example.test` with 10.1.2.3, other
+/// types with no records, and other names with NXDOMAIN
+fn stub_dns_server(queries : usize) -> SocketAddr {
+    let sock = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = sock.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        for _ in 0..queries {
+            let (size, from) = sock.recv_from(&mut buf).unwrap();
+            let query = &buf[..size];
+
+            // question: labels, then type and class
+            let mut pos = 12;
+            let mut labels = Vec::new();
+            while query[pos] != 0 {
+                let len = query[pos] as usize;
+                labels.push(String::from_utf8(query[pos + 1..pos + 1 + len].to_vec()).unwrap());
+                pos += 1 + len;
+            }
+            let question_end = pos + 5;
+            let qtype = read_u16(query, pos + 1);
+
+            let known = labels.join(".") == "example.test";
+            let answer = known && qtype == TYPE_A;
+
+            let mut response = Vec::new();
+            response.extend(query[0..2].iter().cloned());
+            response.extend([0x81, if known { 0x80 } else { 0x83 }].iter().cloned());
+            response.extend([0, 1, 0, if answer { 1 } else { 0 }, 0, 0, 0, 0].iter().cloned());
+            response.extend(query[12..question_end].iter().cloned());
+            if answer {
+                // name pointing at the question, A, IN, TTL, length, address
+                response.extend([0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 1, 2, 3].iter().cloned());
+            }
+
+            sock.send_to(&response, from).unwrap();
+        }
+    });
+
+    addr
+}
+
+#[test]
+fn resolve_localhost_with_system_resolver() {
+    let addrs = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let addrs = addrs.clone();
+        mioco::start(move |mioco| {
+            *addrs.borrow_mut() = try!(mioco.resolve("localhost", 80));
+            Ok(())
+        });
+    }
+
+    let expected : SocketAddr = FromStr::from_str("127.0.0.1:80").unwrap();
+    assert!(addrs.borrow().contains(&expected));
+}
+
+#[test]
+fn resolver_queries_stub_server() {
+    let nameserver = stub_dns_server(4);
+    let results = Rc::new(RefCell::new(None));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let resolver = Resolver::new(nameserver);
+            let found = try!(resolver.lookup(mioco, "example.test", 8080));
+            let missing = resolver.lookup(mioco, "missing.test", 8080).map_err(|e| e.kind());
+            *results.borrow_mut() = Some((found, missing));
+            Ok(())
+        });
+    }
+
+    let (found, missing) = results.borrow_mut().take().unwrap();
+    let expected : SocketAddr = FromStr::from_str("10.1.2.3:8080").unwrap();
+    assert_eq!(found, vec![expected]);
+    assert_eq!(missing, Err(io::ErrorKind::NotFound));
+}
+
+#[test]
+fn resolver_times_out() {
+    // bound, but never answers
+    let silent = net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let nameserver = silent.local_addr().unwrap();
+    let result = Rc::new(RefCell::new(None));
+
+    {
+        let result = result.clone();
+        mioco::start(move |mioco| {
+            let mut resolver = Resolver::new(nameserver);
+            resolver.timeout(100);
+            *result.borrow_mut() = Some(resolver.lookup(mioco, "example.test", 80).map_err(|e| e.kind()));
+            Ok(())
+        });
+    }
+
+    assert_eq!(result.borrow_mut().take().unwrap(), Err(io::ErrorKind::TimedOut));
+}
@@ -0,0 +1,108 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::rc::Rc;
+
+use mioco::MiocoHandle;
+use mioco::timerfd::TimerFd;
+
+fn sleep(mioco : &mut MiocoHandle, ms : u64) -> io::Result<()> {
+    let timer = try!(TimerFd::new());
+    try!(timer.set_oneshot(ms));
+    try!(mioco.wrap(timer).wait());
+    Ok(())
+}
+
+#[test]
+fn parent_waits_for_borrowed_children() {
+    let results = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            // lives on the stack of the parent
+            let finished = RefCell::new(Vec::new());
+
+            try!(mioco.scope(|scope| {
+                for i in 0..3 {
+                    let finished = &finished;
+                    scope.spawn(move |mioco| {
+                        try!(sleep(mioco, 30 - i * 10));
+                        finished.borrow_mut().push(i);
+                        Ok(())
+                    });
+                }
+                Ok(())
+            }));
+
+            *results.borrow_mut() = finished.into_inner();
+            Ok(())
+        });
+    }
+
+    assert_eq!(*results.borrow(), vec![2, 1, 0]);
+}
+
+#[test]
+fn first_child_error_cancels_siblings() {
+    // (error returned by the scope, sibling cancelled)
+    let results = Rc::new(RefCell::new((String::new(), false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let sibling_cancelled = Cell::new(false);
+
+            let res = mioco.scope(|scope| {
+                scope.spawn(|mioco| {
+                    try!(sleep(mioco, 10));
+                    Err(io::Error::new(io::ErrorKind::Other, "child failed"))
+                });
+                let sibling_cancelled = &sibling_cancelled;
+                scope.spawn(move |mioco| {
+                    let res = sleep(mioco, 60_000);
+                    sibling_cancelled.set(res.as_ref().err().map_or(false, mioco::is_cancelled));
+                    res
+                });
+                Ok(())
+            });
+
+            let err = res.err().map_or(String::new(), |e| e.to_string());
+            *results.borrow_mut() = (err, sibling_cancelled.get());
+            Ok(())
+        });
+    }
+
+    assert_eq!(*results.borrow(), ("child failed".to_owned(), true));
+}
+
+#[test]
+fn error_of_scope_body_cancels_children() {
+    let results = Rc::new(RefCell::new((String::new(), false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let child_cancelled = Cell::new(false);
+
+            let res : io::Result<()> = mioco.scope(|scope| {
+                let child_cancelled = &child_cancelled;
+                scope.spawn(move |mioco| {
+                    let res = sleep(mioco, 60_000);
+                    child_cancelled.set(res.as_ref().err().map_or(false, mioco::is_cancelled));
+                    res
+                });
+                Err(io::Error::new(io::ErrorKind::Other, "body failed"))
+            });
+
+            let err = res.err().map_or(String::new(), |e| e.to_string());
+            *results.borrow_mut() = (err, child_cancelled.get());
+            Ok(())
+        });
+    }
+
+    assert_eq!(*results.borrow(), ("body failed".to_owned(), true));
+}
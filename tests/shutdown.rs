@@ -0,0 +1,81 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::Cell;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::thread;
+
+use mioco::net::{TcpListener, TcpStream};
+use mioco::timerfd::TimerFd;
+
+fn any_local_addr() -> SocketAddr {
+    FromStr::from_str("127.0.0.1:0").unwrap()
+}
+
+#[test]
+fn shutdown_interrupts_select_on_listener() {
+    let results = Rc::new(Cell::new((false, false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let listener = try!(TcpListener::bind(&any_local_addr()));
+            let addr = try!(listener.local_addr());
+
+            mioco.spawn(move |mioco| {
+                let interrupted = mioco.select_from(&[listener.index()]).is_err();
+                // `listener` is still open, but does not accept connections
+                let refused = TcpStream::connect(&addr).is_err();
+                results.set((interrupted, refused));
+                Ok(())
+            });
+
+            // let the other coroutine block first
+            let timer = try!(TimerFd::new());
+            try!(timer.set_oneshot(50));
+            try!(mioco.wrap(timer).wait());
+
+            mioco.shutdown();
+            Ok(())
+        });
+    }
+
+    assert_eq!(results.get(), (true, true));
+}
+
+#[test]
+fn shutdown_handle_processes_requests_of_woken_coroutines() {
+    let cancelled = Rc::new(Cell::new(false));
+
+    {
+        let cancelled = cancelled.clone();
+        let mut mioco = mioco::Mioco::new();
+        let shutdown = mioco.shutdown_handle();
+
+        thread::spawn(move || {
+            thread::sleep_ms(50);
+            shutdown.shutdown().unwrap();
+        });
+
+        mioco.start(move |mioco| {
+            let listener = try!(TcpListener::bind(&any_local_addr()));
+
+            let sleeper = mioco.spawn(move |mioco| {
+                let timer = try!(TimerFd::new());
+                try!(timer.set_oneshot(60_000));
+                cancelled.set(mioco.wrap(timer).wait().is_err());
+                Ok(())
+            });
+
+            // fails once shutdown starts, with no other events pending
+            let _ = listener.accept();
+            sleeper.cancel();
+            Ok(())
+        });
+    }
+
+    assert!(cancelled.get());
+}
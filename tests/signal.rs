@@ -0,0 +1,80 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+extern crate libc;
+
+use std::cell::Cell;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+
+use mioco::signal::{SignalFd, SIGUSR1, SIGUSR2};
+
+extern {
+    fn raise(signum : libc::c_int) -> libc::c_int;
+    fn pthread_sigmask(how : libc::c_int, set : *const libc::sigset_t, oldset : *mut libc::sigset_t) -> libc::c_int;
+    fn sigismember(set : *const libc::sigset_t, signum : libc::c_int) -> libc::c_int;
+}
+
+/// Is `signal` blocked in the calling thread
+fn is_blocked(signal : libc::c_int) -> bool {
+    unsafe {
+        let mut set : libc::sigset_t = mem::zeroed();
+        pthread_sigmask(libc::SIG_BLOCK, ptr::null(), &mut set);
+        sigismember(&set, signal) == 1
+    }
+}
+
+#[test]
+fn raised_signal_is_read() {
+    let results = Rc::new(Cell::new((None, None)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let mut signals = mioco.wrap(try!(SignalFd::new(&[SIGUSR1, SIGUSR2])));
+
+            // `raise()` directs the signal to this thread, which blocks it now
+            unsafe { raise(SIGUSR2) };
+            let first = try!(signals.read_signal());
+            unsafe { raise(SIGUSR1) };
+            let second = try!(signals.read_signal());
+
+            results.set((Some(first), Some(second)));
+            Ok(())
+        });
+    }
+
+    assert_eq!(results.get(), (Some(SIGUSR2), Some(SIGUSR1)));
+}
+
+#[test]
+fn nothing_to_read_without_signals() {
+    let read = Rc::new(Cell::new(Some(0)));
+
+    {
+        let read = read.clone();
+        mioco::start(move |_| {
+            let signals = try!(SignalFd::new(&[SIGUSR2]));
+            read.set(try!(signals.try_read_signal()));
+            Ok(())
+        });
+    }
+
+    assert_eq!(read.get(), None);
+}
+
+#[test]
+fn signal_unblocked_after_last_signalfd_is_dropped() {
+    assert!(!is_blocked(SIGUSR1));
+
+    let first = SignalFd::new(&[SIGUSR1]).unwrap();
+    let second = SignalFd::new(&[SIGUSR1]).unwrap();
+    assert!(is_blocked(SIGUSR1));
+
+    drop(first);
+    assert!(is_blocked(SIGUSR1));
+
+    drop(second);
+    assert!(!is_blocked(SIGUSR1));
+}
@@ -0,0 +1,46 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use mioco::{Mioco, MiocoHandle};
+use mioco::timerfd::TimerFd;
+
+fn sleep(mioco : &mut MiocoHandle, ms : u64) -> io::Result<()> {
+    let timer = try!(TimerFd::new());
+    try!(timer.set_oneshot(ms));
+    try!(mioco.wrap(timer).wait());
+    Ok(())
+}
+
+#[test]
+fn stats_handle_is_up_to_date_without_waiting() {
+    let results = Rc::new(RefCell::new(Vec::new()));
+
+    let mut mioco = Mioco::new();
+    let stats = mioco.stats_handle();
+    assert_eq!(stats.get().coroutines_total, 0);
+
+    {
+        let stats = stats.clone();
+        let results = results.clone();
+        mioco.start(move |mioco| {
+            for _ in 0..3 {
+                mioco.spawn(|_| Ok(()));
+            }
+
+            // well below the one second `resumes_per_sec` is measured over
+            try!(sleep(mioco, 20));
+            let published = stats.get();
+            results.borrow_mut().push((published.coroutines, published.coroutines_total));
+            Ok(())
+        });
+    }
+
+    let published = stats.get();
+    results.borrow_mut().push((published.coroutines, published.coroutines_total));
+    assert_eq!(*results.borrow(), vec![(1, 4), (0, 4)]);
+}
@@ -0,0 +1,78 @@
+extern crate mioco;
+extern crate libc;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::io::{RawFd, IntoRawFd};
+use std::rc::Rc;
+
+/// Read standard input of the process to the end, along with its status
+/// flags while wrapped and after
+fn read_stdin() -> (Vec<u8>, bool, libc::c_int, libc::c_int) {
+    let results = Rc::new(RefCell::new((Vec::new(), false, 0, 0)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |_| {
+            let mut data = Vec::new();
+            let (pollable, flags) = {
+                let mut stdin = try!(mioco::stdio::stdin());
+                try!(stdin.read_to_end(&mut data));
+                (stdin.index().is_some(), unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_GETFL) })
+            };
+            let restored = unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_GETFL) };
+
+            *results.borrow_mut() = (data, pollable, flags, restored);
+            Ok(())
+        });
+    }
+
+    let results = results.borrow().clone();
+    results
+}
+
+/// Replace standard input with `fd`, returning the original one
+fn redirect_stdin(fd : RawFd) -> RawFd {
+    unsafe {
+        let original = libc::dup(libc::STDIN_FILENO);
+        assert!(original >= 0);
+        assert_eq!(libc::dup2(fd, libc::STDIN_FILENO), libc::STDIN_FILENO);
+        libc::close(fd);
+        original
+    }
+}
+
+// Standard input is shared by the whole test binary, so both cases run in one test
+#[test]
+fn stdin_redirected_from_pipe_and_file() {
+    // pipe is polled, and set non-blocking only while wrapped
+    let mut pipe = [0; 2];
+    assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, 0);
+    let original = redirect_stdin(pipe[0]);
+    unsafe {
+        libc::write(pipe[1], b"hello".as_ptr() as *const libc::c_void, 5);
+        libc::close(pipe[1]);
+    }
+
+    let (data, pollable, flags, restored) = read_stdin();
+    assert_eq!(data, b"hello".to_vec());
+    assert!(pollable);
+    assert!(flags & libc::O_NONBLOCK != 0);
+    assert!(restored & libc::O_NONBLOCK == 0);
+
+    // regular file is read directly
+    let path = env::temp_dir().join(format!("mioco-stdio-{}", unsafe { libc::getpid() }));
+    fs::File::create(&path).unwrap().write_all(b"world").unwrap();
+    let pipe_dup = redirect_stdin(fs::File::open(&path).unwrap().into_raw_fd());
+    unsafe { libc::close(pipe_dup) };
+    fs::remove_file(&path).unwrap();
+
+    let (data, pollable, _, _) = read_stdin();
+    assert_eq!(data, b"world".to_vec());
+    assert!(!pollable);
+
+    let file_dup = redirect_stdin(original);
+    unsafe { libc::close(file_dup) };
+}
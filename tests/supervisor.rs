@@ -0,0 +1,143 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::rc::Rc;
+
+use mioco::MiocoHandle;
+use mioco::supervisor::{Supervisor, Strategy};
+use mioco::timerfd::TimerFd;
+
+fn sleep(mioco : &mut MiocoHandle, ms : u64) -> io::Result<()> {
+    let timer = try!(TimerFd::new());
+    try!(timer.set_oneshot(ms));
+    try!(mioco.wrap(timer).wait());
+    Ok(())
+}
+
+fn failure() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "child failed")
+}
+
+#[test]
+fn one_for_one_restarts_only_failed_child() {
+    let results = Rc::new(Cell::new((0, 0, false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let failing_runs = Rc::new(Cell::new(0));
+            let steady_runs = Rc::new(Cell::new(0));
+
+            let mut supervisor = Supervisor::new(Strategy::OneForOne);
+            {
+                let runs = failing_runs.clone();
+                supervisor.child(move |_| {
+                    runs.set(runs.get() + 1);
+                    if runs.get() < 3 { Err(failure()) } else { Ok(()) }
+                });
+            }
+            {
+                let runs = steady_runs.clone();
+                supervisor.child(move |mioco| {
+                    runs.set(runs.get() + 1);
+                    sleep(mioco, 50)
+                });
+            }
+
+            let ok = supervisor.run(mioco).is_ok();
+            results.set((failing_runs.get(), steady_runs.get(), ok));
+            Ok(())
+        });
+    }
+
+    assert_eq!(results.get(), (3, 1, true));
+}
+
+#[test]
+fn one_for_all_restarts_every_child() {
+    // (runs of the failing child, runs of its sibling, sibling cancelled, `run()` succeeded)
+    let results = Rc::new(Cell::new((0, 0, false, false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let failing_runs = Rc::new(Cell::new(0));
+            let sibling_runs = Rc::new(Cell::new(0));
+            let sibling_cancelled = Rc::new(Cell::new(false));
+
+            let mut supervisor = Supervisor::new(Strategy::OneForAll);
+            {
+                let runs = failing_runs.clone();
+                supervisor.child(move |mioco| {
+                    runs.set(runs.get() + 1);
+                    try!(sleep(mioco, 10));
+                    if runs.get() == 1 { Err(failure()) } else { Ok(()) }
+                });
+            }
+            {
+                let runs = sibling_runs.clone();
+                let cancelled = sibling_cancelled.clone();
+                supervisor.child(move |mioco| {
+                    runs.set(runs.get() + 1);
+                    if runs.get() > 1 {
+                        return Ok(());
+                    }
+                    let res = sleep(mioco, 60_000);
+                    cancelled.set(res.as_ref().err().map_or(false, mioco::is_cancelled));
+                    res
+                });
+            }
+
+            let ok = supervisor.run(mioco).is_ok();
+            results.set((failing_runs.get(), sibling_runs.get(), sibling_cancelled.get(), ok));
+            Ok(())
+        });
+    }
+
+    assert_eq!(results.get(), (2, 2, true, true));
+}
+
+#[test]
+fn exceeded_intensity_gives_up() {
+    // (runs of the failing child, error of `run()`, sibling cancelled)
+    let results = Rc::new(RefCell::new((0, String::new(), false)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let runs = Rc::new(Cell::new(0));
+            let sibling_cancelled = Rc::new(Cell::new(false));
+
+            let mut supervisor = Supervisor::new(Strategy::OneForOne);
+            supervisor.intensity(2, 60_000);
+            {
+                let runs = runs.clone();
+                supervisor.child(move |_| {
+                    runs.set(runs.get() + 1);
+                    Err(failure())
+                });
+            }
+            {
+                let cancelled = sibling_cancelled.clone();
+                supervisor.child(move |mioco| {
+                    let res = sleep(mioco, 60_000);
+                    cancelled.set(res.as_ref().err().map_or(false, mioco::is_cancelled));
+                    res
+                });
+            }
+
+            let err = supervisor.run(mioco).err().map_or(String::new(), |e| e.to_string());
+            *results.borrow_mut() = (runs.get(), err, sibling_cancelled.get());
+            Ok(())
+        });
+    }
+
+    let results = results.borrow();
+    // started once, and restarted twice
+    assert_eq!(results.0, 3);
+    assert_eq!(results.1, "supervisor restart intensity exceeded");
+    assert!(results.2);
+}
@@ -0,0 +1,117 @@
+extern crate mioco;
+extern crate libc;
+
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use mioco::Traffic;
+use mioco::fs::File;
+use mioco::net::{TcpListener, TcpStream};
+
+#[test]
+fn traffic_counts_files_but_not_thread_pool_wakeups() {
+    let path = env::temp_dir().join(format!("mioco-traffic-{}", unsafe { libc::getpid() }));
+    let traffic = Rc::new(Cell::new(Traffic::default()));
+
+    {
+        let path = path.clone();
+        let traffic = traffic.clone();
+        mioco::start(move |mioco| {
+            {
+                let mut file = try!(File::create(mioco, &path));
+                try!(file.write_all(b"hello"));
+            }
+            {
+                let mut file = try!(File::open(mioco, &path));
+                let mut data = Vec::new();
+                try!(file.read_to_end(&mut data));
+            }
+
+            traffic.set(mioco.traffic());
+            Ok(())
+        });
+    }
+
+    fs::remove_file(&path).unwrap();
+    assert_eq!(traffic.get().bytes_written, 5);
+    assert_eq!(traffic.get().bytes_read, 5);
+}
+
+#[test]
+fn traffic_of_dropped_connections_is_kept() {
+    let written = Rc::new(Cell::new(0));
+
+    {
+        let written = written.clone();
+        mioco::start(move |mioco| {
+            let addr : SocketAddr = FromStr::from_str("127.0.0.1:0").unwrap();
+            let listener = try!(TcpListener::bind(&addr));
+            let addr = try!(listener.local_addr());
+
+            mioco.spawn(move |_| {
+                let (mut conn, _) = try!(listener.accept());
+                let mut buf = Vec::new();
+                try!(conn.read_to_end(&mut buf));
+                Ok(())
+            });
+
+            {
+                let mut stream = try!(TcpStream::connect(&addr));
+                try!(stream.write_all(b"hello"));
+            }
+
+            written.set(mioco.traffic().bytes_written);
+            Ok(())
+        });
+    }
+
+    assert_eq!(written.get(), 5);
+}
+
+#[test]
+fn traffic_stays_with_coroutine_that_made_it() {
+    let results = Rc::new(RefCell::new((Traffic::default(), 0, 0)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let addr : SocketAddr = FromStr::from_str("127.0.0.1:0").unwrap();
+            let listener = try!(TcpListener::bind(&addr));
+            let addr = try!(listener.local_addr());
+
+            let mut stream = try!(TcpStream::connect(&addr));
+            let (mut conn, _) = try!(listener.accept());
+            try!(stream.write_all(b"hello"));
+
+            {
+                let results = results.clone();
+                mioco.spawn(move |mioco| {
+                    try!(stream.write_all(b" world"));
+                    let mut results = results.borrow_mut();
+                    results.1 = mioco.traffic().bytes_written;
+                    results.2 = stream.traffic().bytes_written;
+                    Ok(())
+                });
+            }
+
+            let mut data = Vec::new();
+            try!(conn.read_to_end(&mut data));
+            results.borrow_mut().0 = mioco.traffic();
+            Ok(())
+        });
+    }
+
+    let results = results.borrow();
+    // written by the parent before handing the stream over, and read by it
+    assert_eq!(results.0.bytes_written, 5);
+    assert_eq!(results.0.bytes_read, 11);
+    // written by the child
+    assert_eq!(results.1, 6);
+    // the source itself counts everything since it was wrapped
+    assert_eq!(results.2, 11);
+}
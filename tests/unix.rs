@@ -0,0 +1,209 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+extern crate libc;
+
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use mioco::unix::{UnixListener, UnixStream, UnixDatagram, SocketAddr};
+
+/// Name unique to the test process
+fn unique_name(name : &str) -> String {
+    format!("mioco-unix-{}-{}", name, unsafe { libc::getpid() })
+}
+
+fn socket_path(name : &str) -> PathBuf {
+    let path = env::temp_dir().join(unique_name(name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn stream_over_filesystem_path() {
+    let path = socket_path("stream");
+    let results = Rc::new(RefCell::new((Vec::new(), None, None)));
+
+    {
+        let path = path.clone();
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let listener = try!(UnixListener::bind(&path));
+            let listener_addr = try!(listener.local_addr());
+
+            mioco.spawn(move |_| {
+                let (mut conn, _) = try!(listener.accept());
+                let mut buf = [0u8; 64];
+                let size = try!(conn.read(&mut buf));
+                try!(conn.write_all(&buf[..size]));
+                Ok(())
+            });
+
+            let mut stream = try!(UnixStream::connect(&path));
+            let peer_addr = try!(stream.peer_addr());
+            try!(stream.write_all(b"hello"));
+
+            let mut buf = [0u8; 5];
+            let size = try!(stream.read(&mut buf));
+            *results.borrow_mut() = (buf[..size].to_vec(), Some(listener_addr), Some(peer_addr));
+            Ok(())
+        });
+    }
+
+    let _ = fs::remove_file(&path);
+    let results = results.borrow();
+    assert_eq!(results.0, b"hello".to_vec());
+    assert_eq!(results.1, Some(SocketAddr::Pathname(path.clone())));
+    assert_eq!(results.2, Some(SocketAddr::Pathname(path)));
+}
+
+#[test]
+fn stream_over_abstract_address() {
+    let name = unique_name("abstract").into_bytes();
+    let results = Rc::new(RefCell::new((Vec::new(), None)));
+
+    {
+        let name = name.clone();
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let listener = try!(UnixListener::bind_abstract(&name));
+            let addr = try!(listener.local_addr());
+
+            mioco.spawn(move |_| {
+                let (mut conn, _) = try!(listener.accept());
+                try!(conn.write_all(b"hello"));
+                Ok(())
+            });
+
+            let mut stream = try!(UnixStream::connect_abstract(&name));
+            let mut data = Vec::new();
+            try!(stream.read_to_end(&mut data));
+            *results.borrow_mut() = (data, Some(addr));
+            Ok(())
+        });
+    }
+
+    let results = results.borrow();
+    assert_eq!(results.0, b"hello".to_vec());
+    assert_eq!(results.1, Some(SocketAddr::Abstract(name)));
+}
+
+#[test]
+fn peer_credentials_are_of_this_process() {
+    let creds = Rc::new(Cell::new(None));
+
+    {
+        let creds = creds.clone();
+        mioco::start(move |_| {
+            let (a, _b) = try!(UnixStream::pair());
+            creds.set(Some(try!(a.peer_cred())));
+            Ok(())
+        });
+    }
+
+    let creds = creds.get().unwrap();
+    assert_eq!(creds.pid, unsafe { libc::getpid() });
+    assert_eq!(creds.uid, unsafe { libc::getuid() });
+    assert_eq!(creds.gid, unsafe { libc::getgid() });
+}
+
+#[test]
+fn datagrams_between_named_sockets() {
+    let a_name = unique_name("dgram-a").into_bytes();
+    let b_name = unique_name("dgram-b").into_bytes();
+    let results = Rc::new(RefCell::new((Vec::new(), None)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let a = try!(UnixDatagram::bind_abstract(&a_name));
+            let b = try!(UnixDatagram::bind_abstract(&b_name));
+
+            mioco.spawn(move |_| {
+                let mut buf = [0u8; 64];
+                let (size, addr) = try!(b.recv_from(&mut buf));
+                try!(b.send_to(&buf[..size], &addr));
+                Ok(())
+            });
+
+            try!(a.send_to(b"ping", &SocketAddr::Abstract(b_name)));
+            let mut buf = [0u8; 64];
+            let (size, addr) = try!(a.recv_from(&mut buf));
+            *results.borrow_mut() = (buf[..size].to_vec(), Some(addr));
+            Ok(())
+        });
+    }
+
+    let results = results.borrow();
+    assert_eq!(results.0, b"ping".to_vec());
+    assert_eq!(results.1, Some(SocketAddr::Abstract(unique_name("dgram-b").into_bytes())));
+}
+
+#[test]
+fn connected_datagram_pair() {
+    let received = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let received = received.clone();
+        mioco::start(move |_| {
+            let (a, b) = try!(UnixDatagram::pair());
+            try!(a.send(b"one"));
+            try!(a.send(b"two"));
+
+            // datagram boundaries are kept
+            for _ in 0..2 {
+                let mut buf = [0u8; 64];
+                let size = try!(b.recv(&mut buf));
+                received.borrow_mut().push(buf[..size].to_vec());
+            }
+            Ok(())
+        });
+    }
+
+    assert_eq!(*received.borrow(), vec![b"one".to_vec(), b"two".to_vec()]);
+}
+
+#[test]
+fn passed_fds_work_and_are_close_on_exec() {
+    let results = Rc::new(RefCell::new((0, 0, false, Vec::new(), 0)));
+
+    {
+        let results = results.clone();
+        mioco::start(move |_| {
+            let mut pipe = [0; 2];
+            if unsafe { libc::pipe(pipe.as_mut_ptr()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let (a, b) = try!(UnixStream::pair());
+            try!(a.send_fds(b"fd", &[pipe[1]]));
+            unsafe { libc::close(pipe[1]) };
+
+            let mut buf = [0u8; 16];
+            let mut fds = [-1; 2];
+            let (size, received) = try!(b.recv_fds(&mut buf, &mut fds));
+            let flags = unsafe { libc::fcntl(fds[0], libc::F_GETFD) };
+
+            // the received fd is the writing end of the pipe
+            let written = unsafe { libc::write(fds[0], b"x".as_ptr() as *const libc::c_void, 1) };
+            unsafe { libc::close(fds[0]) };
+            let mut data = [0u8; 1];
+            let read = unsafe { libc::read(pipe[0], data.as_mut_ptr() as *mut libc::c_void, 1) };
+            unsafe { libc::close(pipe[0]) };
+
+            *results.borrow_mut() = (size, received, flags & libc::FD_CLOEXEC != 0, data.to_vec(), written + read);
+            Ok(())
+        });
+    }
+
+    let results = results.borrow();
+    assert_eq!(results.0, 2);
+    assert_eq!(results.1, 1);
+    assert!(results.2);
+    assert_eq!(results.3, b"x".to_vec());
+    assert_eq!(results.4, 2);
+}
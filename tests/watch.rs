@@ -0,0 +1,205 @@
+#![cfg(target_os = "linux")]
+
+extern crate mioco;
+extern crate libc;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use mioco::MiocoHandle;
+use mioco::fs::{Watcher, Tail, EventKind, IN_CREATE, IN_DELETE, IN_MOVED_FROM, IN_MOVED_TO};
+use mioco::timerfd::TimerFd;
+
+/// Fresh directory for a test, unique to the test process
+fn test_dir(name : &str) -> PathBuf {
+    let pid = unsafe { libc::getpid() };
+    let dir = env::temp_dir().join(format!("mioco-watch-{}-{}", name, pid));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn changes_in_directory_are_reported() {
+    let dir = test_dir("dir");
+    let events = Rc::new(RefCell::new(Vec::new()));
+
+    {
+        let dir = dir.clone();
+        let events = events.clone();
+        mioco::start(move |mioco| {
+            let mut watcher = mioco.wrap(try!(Watcher::new()));
+            try!(watcher.add(&dir, IN_CREATE | IN_DELETE | IN_MOVED_FROM | IN_MOVED_TO));
+
+            try!(fs::File::create(dir.join("a")));
+            try!(fs::create_dir(dir.join("d")));
+            try!(fs::rename(dir.join("a"), dir.join("b")));
+            try!(fs::remove_file(dir.join("b")));
+
+            for _ in 0..5 {
+                events.borrow_mut().push(try!(watcher.next_event()));
+            }
+            Ok(())
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    let events = events.borrow();
+    let summary : Vec<_> = events.iter().map(|event| {
+        (event.kind, event.path.file_name().unwrap().to_string_lossy().into_owned(), event.is_dir)
+    }).collect();
+    assert_eq!(summary, vec![
+        (EventKind::Create, "a".to_owned(), false),
+        (EventKind::Create, "d".to_owned(), true),
+        (EventKind::MovedFrom, "a".to_owned(), false),
+        (EventKind::MovedTo, "b".to_owned(), false),
+        (EventKind::Delete, "b".to_owned(), false),
+    ]);
+    assert_eq!(events[0].path, dir.join("a"));
+    assert!(events[2].cookie != 0);
+    assert_eq!(events[2].cookie, events[3].cookie);
+}
+
+#[test]
+fn removed_watch_reports_no_more_changes() {
+    let dir = test_dir("remove");
+    let results = Rc::new(RefCell::new((None, None)));
+
+    {
+        let dir = dir.clone();
+        let results = results.clone();
+        mioco::start(move |mioco| {
+            let mut watcher = mioco.wrap(try!(Watcher::new()));
+            try!(watcher.add(&dir, IN_CREATE));
+            try!(watcher.remove(&dir));
+            let removed = try!(watcher.next_event()).kind;
+
+            try!(fs::File::create(dir.join("a")));
+            let more = try!(watcher.with_raw_mut(|watcher| watcher.try_next_event()));
+
+            *results.borrow_mut() = (Some(removed), more.map(|event| event.kind));
+            Ok(())
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(*results.borrow(), (Some(EventKind::Ignored), None));
+}
+
+fn sleep(mioco : &mut MiocoHandle, ms : u64) -> io::Result<()> {
+    let timer = try!(TimerFd::new());
+    try!(timer.set_oneshot(ms));
+    try!(mioco.wrap(timer).wait());
+    Ok(())
+}
+
+fn append(path : &Path, data : &[u8]) -> io::Result<()> {
+    let mut file = try!(fs::OpenOptions::new().append(true).create(true).open(path));
+    file.write_all(data)
+}
+
+/// Read from `tail` until `len` bytes are collected
+fn read_tail(tail : &mut Tail, len : usize) -> io::Result<String> {
+    let mut data = Vec::new();
+    while data.len() < len {
+        let mut buf = [0u8; 64];
+        let size = try!(tail.read(&mut buf));
+        data.extend(buf[..size].iter().cloned());
+    }
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+#[test]
+fn tail_follows_appended_data() {
+    let dir = test_dir("append");
+    let path = dir.join("log");
+    append(&path, b"one\n").unwrap();
+    let output = Rc::new(RefCell::new(String::new()));
+
+    {
+        let path = path.clone();
+        let output = output.clone();
+        mioco::start(move |mioco| {
+            let mut tail = try!(Tail::open(mioco, &path, 0));
+
+            mioco.spawn(move |mioco| {
+                try!(sleep(mioco, 50));
+                try!(append(&path, b"two\n"));
+                try!(sleep(mioco, 50));
+                append(&path, b"three\n")
+            });
+
+            *output.borrow_mut() = try!(read_tail(&mut tail, 14));
+            Ok(())
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(*output.borrow(), "one\ntwo\nthree\n");
+}
+
+#[test]
+fn tail_starts_over_on_truncation() {
+    let dir = test_dir("truncate");
+    let path = dir.join("log");
+    append(&path, b"first line\n").unwrap();
+    let output = Rc::new(RefCell::new((String::new(), 0)));
+
+    {
+        let path = path.clone();
+        let output = output.clone();
+        mioco::start(move |mioco| {
+            // start at the end of the current content
+            let mut tail = try!(Tail::open(mioco, &path, 11));
+
+            mioco.spawn(move |mioco| {
+                try!(sleep(mioco, 50));
+                try!(fs::File::create(&path));
+                append(&path, b"new\n")
+            });
+
+            let data = try!(read_tail(&mut tail, 4));
+            *output.borrow_mut() = (data, tail.position());
+            Ok(())
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(*output.borrow(), ("new\n".to_owned(), 4));
+}
+
+#[test]
+fn tail_follows_rotated_file_in_another_coroutine() {
+    let dir = test_dir("rotate");
+    let path = dir.join("log");
+    append(&path, b"one\n").unwrap();
+    let output = Rc::new(RefCell::new(String::new()));
+
+    {
+        let dir = dir.clone();
+        let path = path.clone();
+        let output = output.clone();
+        mioco::start(move |mioco| {
+            let tail = try!(Tail::open(mioco, &path, 0));
+
+            mioco.spawn(move |_| {
+                let mut tail = tail;
+                *output.borrow_mut() = try!(read_tail(&mut tail, 14));
+                Ok(())
+            });
+
+            try!(sleep(mioco, 50));
+            // the rest of the old file is read before switching to the new one
+            try!(append(&path, b"two\n"));
+            try!(fs::rename(&path, dir.join("log.1")));
+            append(&path, b"three\n")
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(*output.borrow(), "one\ntwo\nthree\n");
+}